@@ -0,0 +1,428 @@
+use chrono::{DateTime, Utc};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::ExitStatus;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlarmConfig {
+    /// alarm when a sensor sends no temperature for this long
+    #[serde(with = "humantime_serde")]
+    pub stale: Duration,
+    /// alarm when a relay reports another state than commanded for this long
+    #[serde(with = "humantime_serde")]
+    pub mismatch: Duration,
+    /// where to send raised and cleared alarms
+    pub sinks: Vec<Sink>,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            stale: Duration::from_secs(15 * 60),
+            mismatch: Duration::from_secs(60),
+            sinks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmKind {
+    /// a device stopped talking
+    Offline,
+    /// a sensor stopped sending temperatures
+    Stale,
+    /// a relay reports another state than commanded
+    RelayMismatch,
+    /// a zone is above the `max` limit
+    Overheat,
+    /// a zone is below the `frost` limit
+    Frost,
+    /// a zone doesn't warm up while heating
+    HeatingIneffective,
+}
+
+impl AlarmKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Offline => "offline",
+            Self::Stale => "stale",
+            Self::RelayMismatch => "relay_mismatch",
+            Self::Overheat => "overheat",
+            Self::Frost => "frost",
+            Self::HeatingIneffective => "heating_ineffective",
+        }
+    }
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::Offline | Self::Stale | Self::RelayMismatch | Self::HeatingIneffective => {
+                Severity::Warning
+            }
+            Self::Overheat | Self::Frost => Severity::Critical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    /// device or zone
+    pub subject: String,
+    pub severity: Severity,
+    pub message: String,
+    pub active: bool,
+    pub raised: DateTime<Utc>,
+    pub cleared: Option<DateTime<Utc>>,
+}
+
+impl Alarm {
+    pub fn key(&self) -> String {
+        format!("kal/alarm/{}/{}", self.kind.name(), self.subject)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("webhook failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid email: {0}")]
+    Email(#[from] lettre::error::Error),
+    #[error("invalid email address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("smtp failed: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("can't run command: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("command failed: {0}")]
+    Status(ExitStatus),
+}
+
+/// Where to notify alarms, from `severity` up.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Sink {
+    /// POST the alarm as JSON
+    Webhook {
+        url: String,
+        #[serde(default)]
+        severity: Severity,
+    },
+    /// send an email through a relay, without TLS nor authentication
+    Smtp {
+        server: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        from: String,
+        to: Vec<String>,
+        #[serde(default)]
+        severity: Severity,
+    },
+    /// run a program, with the alarm in `KAL_ALARM_*` environment variables
+    Command {
+        command: Vec<String>,
+        #[serde(default)]
+        severity: Severity,
+    },
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+/// So that an unreachable sink doesn't pile up pending notifications.
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl Sink {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Webhook { severity, .. }
+            | Self::Smtp { severity, .. }
+            | Self::Command { severity, .. } => *severity,
+        }
+    }
+
+    pub async fn send(&self, alarm: &Alarm) -> Result<(), SinkError> {
+        match self {
+            Self::Webhook { url, .. } => {
+                reqwest::Client::builder()
+                    .timeout(SINK_TIMEOUT)
+                    .build()?
+                    .post(url)
+                    .json(alarm)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Self::Smtp {
+                server,
+                port,
+                from,
+                to,
+                ..
+            } => {
+                let state = if alarm.active { "raised" } else { "cleared" };
+                let mut message = Message::builder().from(from.parse()?).subject(format!(
+                    "[kal] {} {} {state}",
+                    alarm.kind.name(),
+                    alarm.subject
+                ));
+                for to in to {
+                    message = message.to(to.parse()?);
+                }
+                let message = message.body(alarm.message.clone())?;
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(server)
+                    .port(*port)
+                    .timeout(Some(SINK_TIMEOUT))
+                    .build()
+                    .send(message)
+                    .await?;
+            }
+            Self::Command { command, .. } => {
+                let Some((program, args)) = command.split_first() else {
+                    return Ok(());
+                };
+                let status = tokio::process::Command::new(program)
+                    .args(args)
+                    .env("KAL_ALARM", serde_json::to_string(alarm).unwrap())
+                    .env("KAL_ALARM_KIND", alarm.kind.name())
+                    .env("KAL_ALARM_SUBJECT", &alarm.subject)
+                    .env("KAL_ALARM_SEVERITY", alarm.severity.name())
+                    .env("KAL_ALARM_ACTIVE", alarm.active.to_string())
+                    .env("KAL_ALARM_MESSAGE", &alarm.message)
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(SinkError::Status(status));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Active alarms, raised and cleared once each.
+#[derive(Debug)]
+pub struct Alarms {
+    pub config: AlarmConfig,
+    active: BTreeMap<(AlarmKind, String), Alarm>,
+}
+
+impl Alarms {
+    pub fn new(config: AlarmConfig) -> Self {
+        Self {
+            config,
+            active: BTreeMap::new(),
+        }
+    }
+
+    pub fn active(&self) -> impl Iterator<Item = &Alarm> {
+        self.active.values()
+    }
+
+    pub fn is_active(&self, kind: AlarmKind, subject: &str) -> bool {
+        self.active.contains_key(&(kind, subject.to_string()))
+    }
+
+    /// Return the alarm, unless it's already active.
+    pub fn raise(
+        &mut self,
+        kind: AlarmKind,
+        subject: &str,
+        message: String,
+        now: DateTime<Utc>,
+    ) -> Option<Alarm> {
+        if self.is_active(kind, subject) {
+            return None;
+        }
+        let alarm = Alarm {
+            kind,
+            subject: subject.to_string(),
+            severity: kind.severity(),
+            message,
+            active: true,
+            raised: now,
+            cleared: None,
+        };
+        self.active
+            .insert((kind, subject.to_string()), alarm.clone());
+        Some(alarm)
+    }
+
+    /// Return the cleared alarm, if it was active.
+    pub fn clear(&mut self, kind: AlarmKind, subject: &str, now: DateTime<Utc>) -> Option<Alarm> {
+        let mut alarm = self.active.remove(&(kind, subject.to_string()))?;
+        alarm.active = false;
+        alarm.cleared = Some(now);
+        Some(alarm)
+    }
+
+    /// Send an alarm to the sinks interested in its severity, in the background.
+    pub fn notify(&self, alarm: &Alarm) {
+        for sink in &self.config.sinks {
+            if alarm.severity < sink.severity() {
+                continue;
+            }
+            let (sink, alarm) = (sink.clone(), alarm.clone());
+            tokio::spawn(async move {
+                match sink.send(&alarm).await {
+                    Ok(()) => info!("{} notified", alarm.key()),
+                    Err(e) => warn!("can't notify {}: {e}", alarm.key()),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, extract::State, routing::post};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Accept one mail, and return its DATA.
+    async fn smtp_server(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 localhost\r\n").await.unwrap();
+        let mut data = String::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let reply = match line.to_uppercase() {
+                l if l.starts_with("DATA") => {
+                    write.write_all(b"354 go on\r\n").await.unwrap();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    "250 queued"
+                }
+                l if l.starts_with("QUIT") => {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => "250 ok",
+            };
+            write
+                .write_all(format!("{reply}\r\n").as_bytes())
+                .await
+                .unwrap();
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn test_alarms() {
+        let now = Utc::now();
+        let mut alarms = Alarms::new(AlarmConfig::default());
+        let alarm = alarms
+            .raise(
+                AlarmKind::Overheat,
+                "garage",
+                "31°C > max 30°C".to_string(),
+                now,
+            )
+            .unwrap();
+        assert_eq!(alarm.key(), "kal/alarm/overheat/garage");
+        assert_eq!(alarm.severity, Severity::Critical);
+        assert!(
+            alarms
+                .raise(AlarmKind::Overheat, "garage", String::new(), now)
+                .is_none()
+        );
+        assert_eq!(alarms.active().count(), 1);
+        let cleared = alarms.clear(AlarmKind::Overheat, "garage", now).unwrap();
+        assert!(!cleared.active);
+        assert!(alarms.clear(AlarmKind::Overheat, "garage", now).is_none());
+
+        // webhook
+        let (tx, mut rx) = mpsc::channel(1);
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    async |State(tx): State<mpsc::Sender<serde_json::Value>>, Json(v)| {
+                        tx.send(v).await.unwrap();
+                    },
+                ),
+            )
+            .with_state(tx);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let sink = Sink::Webhook {
+            url,
+            severity: Severity::Warning,
+        };
+        sink.send(&alarm).await.unwrap();
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received["kind"], "overheat");
+        assert_eq!(received["severity"], "critical");
+
+        // smtp
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(smtp_server(listener));
+        let sink = Sink::Smtp {
+            server: "127.0.0.1".to_string(),
+            port,
+            from: "kal@localhost".to_string(),
+            to: vec!["root@localhost".to_string()],
+            severity: Severity::Critical,
+        };
+        sink.send(&alarm).await.unwrap();
+        let data = server.await.unwrap();
+        assert!(data.contains("Subject: [kal] overheat garage raised"));
+
+        // command
+        let path = std::env::temp_dir().join(format!("kal-alarm-{}", std::process::id()));
+        let sink = Sink::Command {
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!(
+                    "echo $KAL_ALARM_KIND $KAL_ALARM_SEVERITY $KAL_ALARM_ACTIVE > {}",
+                    path.display()
+                ),
+            ],
+            severity: Severity::Info,
+        };
+        sink.send(&cleared).await.unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "overheat critical false\n");
+        let sink = Sink::Command {
+            command: vec!["false".to_string()],
+            severity: Severity::Info,
+        };
+        assert!(matches!(sink.send(&alarm).await, Err(SinkError::Status(_))));
+    }
+}
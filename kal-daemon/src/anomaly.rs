@@ -0,0 +1,131 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::{Room, Temperature};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnomalyConfig {
+    /// how long a zone heats before its temperature response is judged
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    /// smallest rise expected over a window, in °C, on top of the change
+    /// the zone model predicts without heating when the outdoor temperature
+    /// is known
+    pub rise: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(3600),
+            rise: 0.1,
+        }
+    }
+}
+
+/// Temperature of a heating zone at the start of a window.
+#[derive(Debug, Clone, Copy)]
+pub struct Run {
+    since: DateTime<Utc>,
+    start: Temperature,
+}
+
+impl AnomalyConfig {
+    /// Change to expect over a window from `start`: on a cold day, the room
+    /// may still cool while heating, just slower than without.
+    pub fn expected(&self, model: &Room, start: Temperature, outdoor: Option<Temperature>) -> f64 {
+        let Some(outdoor) = outdoor else {
+            return self.rise;
+        };
+        let mut room = Room {
+            temperature: start.into(),
+            outdoor: outdoor.into(),
+            heating: false,
+            ..model.clone()
+        };
+        room.step(self.window);
+        room.temperature - f64::from(start) + self.rise
+    }
+
+    /// Follow the temperature of a zone while it heats. At the end of each
+    /// window, return whether it rose enough, or why not.
+    pub fn check(
+        &self,
+        run: &mut Option<Run>,
+        heating: bool,
+        t: Option<Temperature>,
+        model: &Room,
+        outdoor: Option<Temperature>,
+        now: DateTime<Utc>,
+    ) -> Option<Result<(), String>> {
+        let (true, Some(t)) = (heating, t) else {
+            *run = None;
+            return None;
+        };
+        let Run { since, start } = *run.get_or_insert(Run {
+            since: now,
+            start: t,
+        });
+        if now - since < TimeDelta::from_std(self.window).unwrap() {
+            return None;
+        }
+        *run = Some(Run {
+            since: now,
+            start: t,
+        });
+        let expected = self.expected(model, start, outdoor);
+        let change = f64::from(t) - f64::from(start);
+        Some(match change < expected {
+            true => Err(format!(
+                "changed {change:+.1}°C in {} while heating, expected {expected:+.1}°C",
+                humantime::format_duration(self.window)
+            )),
+            false => Ok(()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anomaly() {
+        let config = AnomalyConfig::default();
+        let model = Room::default();
+        let t0 = Utc::now();
+        let at = |m| t0 + TimeDelta::minutes(m);
+        let mut run = None;
+        let mut check = |heating, t: f64, m| {
+            config.check(&mut run, heating, Some(t.into()), &model, None, at(m))
+        };
+
+        // rising
+        assert_eq!(check(true, 15.0, 0), None);
+        assert_eq!(check(true, 15.1, 30), None);
+        assert_eq!(check(true, 15.3, 60), Some(Ok(())));
+
+        // then the gas runs out
+        assert_eq!(check(true, 15.3, 90), None);
+        assert!(check(true, 15.2, 120).unwrap().is_err());
+
+        // switching off starts over
+        assert_eq!(check(false, 15.2, 130), None);
+        assert_eq!(check(true, 15.0, 140), None);
+        assert_eq!(check(true, 15.0, 190), None);
+
+        // on a cold day, slowing the cooling down is enough
+        let cold = config.expected(&model, 15.0.into(), Some((-10.0).into()));
+        assert!(cold < 0.0);
+        let mut run = None;
+        let mut check = |t: f64, m| {
+            let outdoor = Some((-10.0).into());
+            config.check(&mut run, true, Some(t.into()), &model, outdoor, at(m))
+        };
+        assert_eq!(check(15.0, 0), None);
+        assert_eq!(check(14.9, 60), Some(Ok(())));
+        assert!(check(12.0, 120).unwrap().is_err());
+    }
+}
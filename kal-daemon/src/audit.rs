@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// entries kept in memory when there is no `state_dir`
+const MEMORY: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// rotate `audit.jsonl` when it grows over this many bytes
+    pub max_size: u64,
+    /// number of rotated files to keep
+    pub keep: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 1 << 20,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Event {
+    Command {
+        key: String,
+        payload: String,
+        source: Option<String>,
+        result: String,
+    },
+    Relay {
+        zone: String,
+        on: bool,
+        reason: String,
+    },
+    Cooler {
+        zone: String,
+        on: bool,
+        reason: String,
+    },
+    Stage {
+        zone: String,
+        relay: String,
+        on: bool,
+        reason: String,
+    },
+    Source {
+        source: String,
+        relay: String,
+        on: bool,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Append-only log of commands and relay decisions.
+#[derive(Debug)]
+pub struct Audit {
+    config: AuditConfig,
+    dir: Option<PathBuf>,
+    memory: VecDeque<Entry>,
+}
+
+impl Audit {
+    /// Entries go to `<state_dir>/audit.jsonl`, rotated to `audit.jsonl.1` etc.
+    pub fn new(config: AuditConfig, state_dir: Option<PathBuf>) -> Self {
+        Self {
+            config,
+            dir: state_dir,
+            memory: VecDeque::new(),
+        }
+    }
+
+    fn path(&self, n: usize) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(match n {
+            0 => dir.join("audit.jsonl"),
+            n => dir.join(format!("audit.jsonl.{n}")),
+        })
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        for n in (0..self.config.keep).rev() {
+            let from = self.path(n).unwrap();
+            if from.exists() {
+                std::fs::rename(from, self.path(n + 1).unwrap())?;
+            }
+        }
+        if self.config.keep == 0 {
+            std::fs::remove_file(self.path(0).unwrap())?;
+        }
+        Ok(())
+    }
+
+    fn append(&self, entry: &Entry) -> std::io::Result<()> {
+        let path = self.path(0).unwrap();
+        if std::fs::metadata(&path).is_ok_and(|m| m.len() >= self.config.max_size) {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(entry).unwrap())
+    }
+
+    pub fn log(&mut self, time: DateTime<Utc>, event: Event) {
+        let entry = Entry { time, event };
+        if self.dir.is_some() {
+            if let Err(e) = self.append(&entry) {
+                warn!("can't write audit log: {e}");
+            }
+        } else {
+            if self.memory.len() == MEMORY {
+                self.memory.pop_front();
+            }
+            self.memory.push_back(entry);
+        }
+    }
+
+    /// Entries between two times included, oldest first.
+    pub fn query(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Vec<Entry> {
+        let keep = |e: &Entry| from.is_none_or(|f| f <= e.time) && to.is_none_or(|t| e.time <= t);
+        if self.dir.is_none() {
+            return self.memory.iter().filter(|e| keep(e)).cloned().collect();
+        }
+        let mut entries = Vec::new();
+        for n in (0..=self.config.keep).rev() {
+            let Ok(file) = File::open(self.path(n).unwrap()) else {
+                continue;
+            };
+            for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+                match serde_json::from_str::<Entry>(&line) {
+                    Ok(entry) if keep(&entry) => entries.push(entry),
+                    Ok(_) => {}
+                    Err(e) => warn!("invalid audit entry {line:?}: {e}"),
+                }
+            }
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_audit_rotation() {
+        let dir = std::env::temp_dir().join(format!("kal-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = AuditConfig {
+            max_size: 200,
+            keep: 2,
+        };
+        let mut audit = Audit::new(config, Some(dir.clone()));
+        let t0 = Utc::now();
+        for i in 0..20 {
+            let event = Event::Relay {
+                zone: "garage".to_string(),
+                on: i % 2 == 0,
+                reason: format!("decision {i}"),
+            };
+            audit.log(t0 + TimeDelta::minutes(i), event);
+        }
+        assert!(dir.join("audit.jsonl.2").exists());
+        assert!(!dir.join("audit.jsonl.3").exists());
+
+        // older entries were rotated away, the rest is in order
+        let entries = audit.query(None, None);
+        assert!(entries.len() < 20);
+        assert!(entries.is_sorted_by_key(|e| e.time));
+        assert_eq!(entries.last().unwrap().time, t0 + TimeDelta::minutes(19));
+
+        let range = audit.query(
+            Some(t0 + TimeDelta::minutes(15)),
+            Some(t0 + TimeDelta::minutes(17)),
+        );
+        assert_eq!(range.len(), 3);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
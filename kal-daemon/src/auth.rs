@@ -0,0 +1,153 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// shared secrets, by key id
+    pub keys: BTreeMap<String, String>,
+    /// accepted clock skew, and how long nonces are remembered
+    #[serde(default = "default_window", with = "humantime_serde")]
+    pub window: Duration,
+}
+
+fn default_window() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AuthError {
+    #[error("missing signature")]
+    Missing,
+    #[error("malformed signature")]
+    Malformed,
+    #[error("unknown key {0:?}")]
+    UnknownKey(String),
+    #[error("bad signature")]
+    BadSignature,
+    #[error("timestamp out of window")]
+    Expired,
+    #[error("replayed nonce")]
+    Replay,
+}
+
+fn mac(secret: &str, key: &str, timestamp: i64, nonce: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{key}\n{timestamp}\n{nonce}\n{payload}").as_bytes());
+    mac
+}
+
+/// Attachment `<key id>:<unix timestamp>:<nonce>:<hex hmac-sha256>` for a command.
+pub fn sign(key_id: &str, secret: &str, key: &str, payload: &str, now: DateTime<Utc>) -> String {
+    let timestamp = now.timestamp();
+    let nonce = hex::encode(rand::random::<[u8; 12]>());
+    let signature = mac(secret, key, timestamp, &nonce, payload).finalize();
+    let signature = hex::encode(signature.into_bytes());
+    format!("{key_id}:{timestamp}:{nonce}:{signature}")
+}
+
+/// Check HMAC signed commands, rejecting replays.
+#[derive(Debug)]
+pub struct Auth {
+    config: AuthConfig,
+    nonces: HashMap<String, DateTime<Utc>>,
+}
+
+impl Auth {
+    pub fn new(config: AuthConfig) -> Self {
+        Self {
+            config,
+            nonces: HashMap::new(),
+        }
+    }
+
+    pub fn verify(
+        &mut self,
+        key: &str,
+        payload: &str,
+        attachment: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<(), AuthError> {
+        let attachment = attachment.ok_or(AuthError::Missing)?;
+        let [key_id, timestamp, nonce, signature] = attachment
+            .splitn(4, ':')
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| AuthError::Malformed)?;
+        let timestamp: i64 = timestamp.parse().map_err(|_| AuthError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| AuthError::Malformed)?;
+        let secret = self
+            .config
+            .keys
+            .get(key_id)
+            .ok_or_else(|| AuthError::UnknownKey(key_id.to_string()))?;
+        mac(secret, key, timestamp, nonce, payload)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::BadSignature)?;
+
+        let window = TimeDelta::from_std(self.config.window).unwrap();
+        let time = DateTime::from_timestamp(timestamp, 0).ok_or(AuthError::Malformed)?;
+        if (now - time).abs() > window {
+            return Err(AuthError::Expired);
+        }
+        // nonces older than the window can't pass the timestamp check anymore
+        self.nonces.retain(|_, t| now - *t <= window * 2);
+        if self.nonces.insert(nonce.to_string(), now).is_some() {
+            return Err(AuthError::Replay);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth() {
+        let mut auth = Auth::new(AuthConfig {
+            keys: BTreeMap::from([("kalctl".to_string(), "secret".to_string())]),
+            window: default_window(),
+        });
+        let now = Utc::now();
+        let key = "kal/cmnd/daemon/mode";
+        let signed = sign("kalctl", "secret", key, "On", now);
+
+        assert_eq!(auth.verify(key, "On", None, now), Err(AuthError::Missing));
+        assert_eq!(
+            auth.verify(key, "Off", Some(&signed), now),
+            Err(AuthError::BadSignature)
+        );
+        assert_eq!(
+            auth.verify("kal/cmnd/daemon/insert", "On", Some(&signed), now),
+            Err(AuthError::BadSignature)
+        );
+        let later = now + TimeDelta::minutes(1);
+        assert_eq!(
+            auth.verify(key, "On", Some(&signed), later),
+            Err(AuthError::Expired)
+        );
+        assert_eq!(auth.verify(key, "On", Some(&signed), now), Ok(()));
+        assert_eq!(
+            auth.verify(key, "On", Some(&signed), now),
+            Err(AuthError::Replay)
+        );
+
+        let other = sign("other", "secret", key, "On", now);
+        assert_eq!(
+            auth.verify(key, "On", Some(&other), now),
+            Err(AuthError::UnknownKey("other".to_string()))
+        );
+        assert_eq!(
+            auth.verify(key, "On", Some("kalctl:nope"), now),
+            Err(AuthError::Malformed)
+        );
+    }
+}
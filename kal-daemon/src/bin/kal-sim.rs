@@ -0,0 +1,129 @@
+use clap::Parser;
+use kal_daemon::Room;
+use log::{debug, info, warn};
+use std::time::Duration;
+use tokio::time::interval;
+
+/// Simulate a heated room: publish its temperature and obey its relay.
+#[derive(Parser, Debug)]
+struct Args {
+    /// zenoh endpoints to connect to
+    #[arg(long)]
+    connect: Vec<String>,
+    /// zenoh endpoints to listen on, eg. to let kal-daemon connect without zenohd
+    #[arg(long)]
+    listen: Vec<String>,
+    /// device publishing kal/tele/<sensor>/temperature
+    #[arg(long, default_value = "tasmota_43D8FD")]
+    sensor: String,
+    /// device subscribed to kal/cmnd/<relay>/relay
+    #[arg(long, default_value = "garage")]
+    relay: String,
+    /// simulated seconds per real second
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    speed: f64,
+    /// simulated time between two temperature readings
+    #[arg(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    period: Duration,
+    /// initial room temperature, in °C
+    #[arg(long, default_value_t = 15.0)]
+    temperature: f64,
+    /// outdoor temperature, in °C
+    #[arg(long, default_value_t = 5.0)]
+    outdoor: f64,
+    /// heat loss, in W/K
+    #[arg(long, default_value_t = 150.0)]
+    heat_loss: f64,
+    /// heater power, in W
+    #[arg(long, default_value_t = 2000.0)]
+    heater_power: f64,
+    /// thermal mass, in J/K
+    #[arg(long, default_value_t = 5e6)]
+    capacity: f64,
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(speed) => Err(format!("speed must be positive, not {speed}")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_relay(payload: &str, current: bool) -> bool {
+    match payload {
+        "ON" | "On" | "on" | "TRUE" | "True" | "true" | "1" => true,
+        "OFF" | "Off" | "off" | "FALSE" | "False" | "false" | "0" => false,
+        _ => !current,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let args = Args::parse();
+    let mut room = Room {
+        temperature: args.temperature,
+        outdoor: args.outdoor,
+        heat_loss: args.heat_loss,
+        heater_power: args.heater_power,
+        capacity: args.capacity,
+        heating: false,
+    };
+
+    let mut config = zenoh::Config::default();
+    if !args.connect.is_empty() {
+        config
+            .insert_json5(
+                "connect/endpoints",
+                &serde_json::to_string(&args.connect).unwrap(),
+            )
+            .unwrap();
+    }
+    if !args.listen.is_empty() {
+        config
+            .insert_json5(
+                "listen/endpoints",
+                &serde_json::to_string(&args.listen).unwrap(),
+            )
+            .unwrap();
+    }
+    let session = zenoh::open(config).await.unwrap();
+    let relay_sub = session
+        .declare_subscriber(format!("kal/cmnd/{}/relay", args.relay))
+        .await
+        .unwrap();
+    let temperature_key = format!("kal/tele/{}/temperature", args.sensor);
+    let relay_key = format!("kal/tele/{}/relay", args.relay);
+    for device in [&args.sensor, &args.relay] {
+        let key = format!("kal/tele/{device}/hello");
+        session.put(key, "1").await.unwrap();
+    }
+
+    info!("simulating {room:?} at {}x", args.speed);
+    let mut tick = interval(args.period.div_f64(args.speed));
+    loop {
+        tokio::select! {
+            sample = relay_sub.recv_async() => {
+                if let Ok(sample) = sample
+                    && let Ok(payload) = sample.payload().try_to_string()
+                {
+                    let heating = parse_relay(&payload, room.heating);
+                    if heating != room.heating {
+                        info!("relay {heating} at {}", room.temperature());
+                        room.heating = heating;
+                    }
+                    session.put(&relay_key, room.heating.to_string()).await.unwrap();
+                }
+            }
+            _ = tick.tick() => {
+                room.step(args.period);
+                debug!("{} (heating: {})", room.temperature(), room.heating);
+                let payload = format!("{:.2}", room.temperature);
+                if let Err(e) = session.put(&temperature_key, payload).await {
+                    warn!("can't publish temperature: {e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,677 @@
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand};
+use kal_daemon::{Anchor, Mode, sign};
+use serde_json::{Value, json};
+use std::time::Duration;
+use zenoh::Session;
+
+/// Operate kal-daemon over zenoh.
+#[derive(Parser, Debug)]
+struct Args {
+    /// zenoh endpoints to connect to
+    #[arg(long, default_value = "tcp/127.0.0.1:7447")]
+    connect: Vec<String>,
+    /// machine readable output
+    #[arg(long)]
+    json: bool,
+    /// sign commands with this key id, when the daemon requires it
+    #[arg(long, env = "KAL_KEY_ID", requires = "secret")]
+    key_id: Option<String>,
+    /// secret of that key
+    #[arg(long, env = "KAL_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show mode, setpoint and zones
+    Status,
+    /// Set the mode
+    Mode {
+        #[arg(value_parser = ["Auto", "On", "Off", "Heat", "Cool", "HeatCool"], ignore_case = true)]
+        mode: String,
+    },
+    /// Hold a temperature or a preset until the next schedule point, or cancel with "cancel"
+    Override {
+        setpoint: String,
+        /// hold for this long instead, eg. "90m"
+        #[arg(long = "for", value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// List, add or remove schedule points
+    #[command(subcommand)]
+    Schedule(ScheduleCommand),
+    /// List, switch, copy, rename or delete schedule profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// List, set or remove named setpoints
+    #[command(subcommand)]
+    Preset(PresetCommand),
+    /// Show daily heating reports
+    Report {
+        /// first day, as YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// last day, as YYYY-MM-DD
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+    /// Show the scheduled setpoint curve, and the next change
+    Setpoints {
+        /// RFC 3339 time or YYYY-MM-DD, now by default
+        #[arg(long)]
+        from: Option<String>,
+        /// RFC 3339 time or YYYY-MM-DD, a day later by default
+        #[arg(long)]
+        to: Option<String>,
+        /// eg. "1h"
+        #[arg(long, default_value = "1h")]
+        step: String,
+    },
+    /// Show the planned relay timeline of each zone
+    Plan {
+        /// how far ahead, in hours
+        #[arg(long, default_value_t = 24)]
+        hours: u32,
+    },
+    /// Show the audit log of commands and relay decisions
+    Audit {
+        /// RFC 3339 time or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        /// RFC 3339 time or YYYY-MM-DD
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// List, add or cancel commands to run later
+    #[command(subcommand)]
+    Queue(QueueCommand),
+    /// Show active alarms
+    Alarms,
+    /// Print live telemetry, commands and alarms
+    Watch {
+        #[arg(default_value = "kal/**")]
+        key: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum QueueCommand {
+    List,
+    /// Run a daemon command once, eg. "add 'fri 18:00' mode Off" or "add '2026-11-02 06:00' override eco"
+    Add {
+        /// RFC 3339 time, or "YYYY-MM-DD HH:MM", "<weekday> HH:MM" or "HH:MM" in the daemon's timezone
+        when: String,
+        command: String,
+        payload: String,
+    },
+    /// Cancel a pending command
+    Cancel {
+        id: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PresetCommand {
+    List,
+    /// Add or change a preset, eg. "set comfort 19.5"
+    Set {
+        name: String,
+        temperature: f64,
+    },
+    /// Remove a preset no schedule point uses
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ScheduleCommand {
+    List {
+        /// instead of the active profile
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Add or replace a point, eg. "add 07:00 19.5", "add 07:00 comfort" or "add sunset-30 20"
+    Add {
+        time: Anchor,
+        setpoint: String,
+        /// instead of the active profile
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Remove a point, eg. "remove 07:00"
+    Remove {
+        time: Anchor,
+        /// instead of the active profile
+        #[arg(long)]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileCommand {
+    List,
+    /// Switch to a profile
+    Use {
+        name: String,
+    },
+    /// Copy a profile to a new one
+    Copy {
+        from: String,
+        to: String,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    /// Delete a profile, except the active one
+    Delete {
+        name: String,
+    },
+}
+
+async fn query(session: &Session, key: &str) -> Value {
+    let replies = session
+        .get(key)
+        .timeout(Duration::from_secs(3))
+        .await
+        .unwrap();
+    while let Ok(reply) = replies.recv_async().await {
+        match reply.result() {
+            Ok(sample) => {
+                if let Ok(payload) = sample.payload().try_to_string()
+                    && let Ok(value) = serde_json::from_str(&payload)
+                {
+                    return value;
+                }
+            }
+            Err(error) => {
+                let error = error.payload().try_to_string();
+                eprintln!("{key}: {}", error.as_deref().unwrap_or("<binary>"));
+                std::process::exit(1);
+            }
+        }
+    }
+    eprintln!("no reply on {key}, is kal-daemon running?");
+    std::process::exit(1);
+}
+
+async fn put(session: &Session, args: &Args, key: &str, payload: String) {
+    let mut put = session.put(key, payload.as_str());
+    if let (Some(key_id), Some(secret)) = (&args.key_id, &args.secret) {
+        put = put.attachment(sign(key_id, secret, key, &payload, Utc::now()));
+    }
+    put.await.unwrap();
+    if args.json {
+        println!("{}", json!({ "key": key, "payload": payload }));
+    } else {
+        println!("{key} ← {payload}");
+    }
+}
+
+/// Minutes since midnight as HH:MM, or a solar point as is.
+fn hhmm(time: &Value) -> String {
+    if let Some(solar) = time.as_str() {
+        return solar.to_string();
+    }
+    let m = time.as_u64().unwrap_or_default();
+    format!("{:02}:{:02}", m / 60, m % 60)
+}
+
+/// The daemon's timezone, in which its times are shown.
+async fn timezone(session: &Session) -> Tz {
+    let status = query(session, "kal/stat/daemon/status").await;
+    status_timezone(&status)
+}
+
+fn status_timezone(status: &Value) -> Tz {
+    status["timezone"]
+        .as_str()
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Format a JSON time in the daemon's timezone.
+fn local(time: &Value, tz: Tz, format: &str) -> String {
+    time.as_str()
+        .and_then(|t| t.parse::<DateTime<Utc>>().ok())
+        .map_or("?".to_string(), |t| {
+            t.with_timezone(&tz).format(format).to_string()
+        })
+}
+
+fn print_next(next: &Value, tz: Tz) {
+    if let Some(temperature) = next["temperature"].as_f64() {
+        println!(
+            "next: {temperature}°C at {}",
+            local(&next["time"], tz, "%a %H:%M")
+        );
+    }
+}
+
+fn print_status(status: &Value) {
+    println!("mode: {}", status["mode"].as_str().unwrap_or("?"));
+    println!("profile: {}", status["profile"].as_str().unwrap_or("?"));
+    let tz = status_timezone(status);
+    println!("timezone: {tz}");
+    print!("setpoint: {}°C", status["setpoint"]);
+    if status["override"]["until"].is_string() {
+        let preset = status["override"]["preset"]
+            .as_str()
+            .map_or(String::new(), |p| format!("{p} "));
+        print!(
+            " ({preset}override until {})",
+            local(&status["override"]["until"], tz, "%F %R")
+        );
+    }
+    if status["presence"]["away"].as_bool() == Some(true) {
+        print!(" (away)");
+    }
+    if status["solar"]["boost"].as_bool() == Some(true) {
+        print!(" (solar boost)");
+    }
+    println!();
+    print_next(&status["next"], tz);
+    if matches!(status["mode"].as_str(), Some("Cool" | "HeatCool")) {
+        println!("cool setpoint: {}°C", status["cool_setpoint"]);
+    }
+    for zone in status["zones"].as_array().into_iter().flatten() {
+        let relay = match zone["heating"].as_bool() {
+            Some(true) => "On",
+            Some(false) => "Off",
+            None => "?",
+        };
+        let temperature = match zone["temperature"].as_f64() {
+            Some(t) => format!("{t}°C"),
+            None => "?".to_string(),
+        };
+        let cooler = match zone["cooling"].as_bool() {
+            Some(true) => ", cooler On",
+            Some(false) => ", cooler Off",
+            None => "",
+        };
+        let stages: String = zone["stages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|s| {
+                let on = match s["on"].as_bool() {
+                    Some(true) => "On",
+                    Some(false) => "Off",
+                    None => "?",
+                };
+                format!(", {} {on}", s["relay"].as_str().unwrap_or("?"))
+            })
+            .collect();
+        let limit = zone["limit"]
+            .as_str()
+            .map_or(String::new(), |l| format!(" ({l}!)"));
+        println!(
+            "{}: {temperature}, relay {relay}{stages}{cooler}{limit}",
+            zone["name"].as_str().unwrap_or("?")
+        );
+    }
+    for source in status["sources"].as_array().into_iter().flatten() {
+        let state = |v: &Value| match v.as_bool() {
+            Some(true) => "On",
+            Some(false) => "Off",
+            None => "?",
+        };
+        let pump = match source["pump"].as_str() {
+            Some(pump) => format!(", {pump} {}", state(&source["pumping"])),
+            None => String::new(),
+        };
+        println!(
+            "{}: {} {}{pump}",
+            source["name"].as_str().unwrap_or("?"),
+            source["relay"].as_str().unwrap_or("?"),
+            state(&source["on"]),
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let args = Args::parse();
+    let mut config = zenoh::Config::default();
+    config
+        .insert_json5(
+            "connect/endpoints",
+            &serde_json::to_string(&args.connect).unwrap(),
+        )
+        .unwrap();
+    let session = zenoh::open(config).await.unwrap();
+
+    match &args.command {
+        Command::Status => {
+            let status = query(&session, "kal/stat/daemon/status").await;
+            if args.json {
+                println!("{status}");
+            } else {
+                print_status(&status);
+            }
+        }
+        Command::Mode { mode } => {
+            let mode = Mode::from(mode.as_str());
+            put(&session, &args, "kal/cmnd/daemon/mode", mode.to_string()).await;
+        }
+        Command::Override { setpoint, duration } => {
+            let payload = match duration {
+                Some(d) => format!("{setpoint}|{}", d.as_secs().div_ceil(60)),
+                None => setpoint.clone(),
+            };
+            put(&session, &args, "kal/cmnd/daemon/override", payload).await;
+        }
+        Command::Schedule(ScheduleCommand::List { profile }) => {
+            let key = match profile {
+                Some(profile) => format!("kal/stat/daemon/schedule?profile={profile}"),
+                None => "kal/stat/daemon/schedule".to_string(),
+            };
+            let points = query(&session, &key).await;
+            if args.json {
+                println!("{points}");
+            } else {
+                for point in points.as_array().into_iter().flatten() {
+                    match point[2].as_str() {
+                        Some(preset) => {
+                            println!("{} {}°C ({preset})", hhmm(&point[0]), point[1])
+                        }
+                        None => println!("{} {}°C", hhmm(&point[0]), point[1]),
+                    }
+                }
+            }
+        }
+        Command::Schedule(ScheduleCommand::Add {
+            time,
+            setpoint,
+            profile,
+        }) => {
+            let mut payload = format!("{time}|{setpoint}");
+            if let Some(profile) = profile {
+                payload.push_str(&format!("|{profile}"));
+            }
+            put(&session, &args, "kal/cmnd/daemon/insert", payload).await;
+        }
+        Command::Schedule(ScheduleCommand::Remove { time, profile }) => {
+            let mut payload = time.to_string();
+            if let Some(profile) = profile {
+                payload.push_str(&format!("|{profile}"));
+            }
+            put(&session, &args, "kal/cmnd/daemon/remove", payload).await;
+        }
+        Command::Profile(ProfileCommand::List) => {
+            let profiles = query(&session, "kal/stat/daemon/profiles").await;
+            if args.json {
+                println!("{profiles}");
+            } else {
+                for name in profiles["profiles"].as_array().into_iter().flatten() {
+                    let name = name.as_str().unwrap_or("?");
+                    let active = if profiles["active"] == name { " *" } else { "" };
+                    println!("{name}{active}");
+                }
+            }
+        }
+        Command::Profile(ProfileCommand::Use { name }) => {
+            put(&session, &args, "kal/cmnd/daemon/profile", name.clone()).await;
+        }
+        Command::Profile(ProfileCommand::Copy { from, to }) => {
+            let payload = format!("{from}|{to}");
+            put(&session, &args, "kal/cmnd/daemon/copy_profile", payload).await;
+        }
+        Command::Profile(ProfileCommand::Rename { from, to }) => {
+            let payload = format!("{from}|{to}");
+            put(&session, &args, "kal/cmnd/daemon/rename_profile", payload).await;
+        }
+        Command::Profile(ProfileCommand::Delete { name }) => {
+            put(
+                &session,
+                &args,
+                "kal/cmnd/daemon/delete_profile",
+                name.clone(),
+            )
+            .await;
+        }
+        Command::Preset(PresetCommand::List) => {
+            let presets = query(&session, "kal/stat/daemon/presets").await;
+            if args.json {
+                println!("{presets}");
+            } else {
+                for (name, temperature) in presets.as_object().into_iter().flatten() {
+                    println!("{name} {temperature}°C");
+                }
+            }
+        }
+        Command::Preset(PresetCommand::Set { name, temperature }) => {
+            let payload = format!("{name}|{temperature}");
+            put(&session, &args, "kal/cmnd/daemon/preset", payload).await;
+        }
+        Command::Preset(PresetCommand::Remove { name }) => {
+            put(&session, &args, "kal/cmnd/daemon/preset", name.clone()).await;
+        }
+        Command::Report { from, to } => {
+            let mut key = "kal/stat/daemon/report?".to_string();
+            if let Some(from) = from {
+                key.push_str(&format!("from={from};"));
+            }
+            if let Some(to) = to {
+                key.push_str(&format!("to={to};"));
+            }
+            let reports = query(&session, &key).await;
+            if args.json {
+                println!("{reports}");
+            } else {
+                for r in reports.as_array().into_iter().flatten() {
+                    let stages: String = r["stage_runtime_hours"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|h| format!(" + {:.1}h", h.as_f64().unwrap_or_default()))
+                        .collect();
+                    println!(
+                        "{} {}: {:.1}h{stages} ({:.0}%), {:.1} kWh, cost {:.2}, {} average, {} degree-days",
+                        r["date"].as_str().unwrap_or("?"),
+                        r["zone"].as_str().unwrap_or("?"),
+                        r["runtime_hours"].as_f64().unwrap_or_default(),
+                        r["duty_cycle"].as_f64().unwrap_or_default() * 100.0,
+                        r["energy_kwh"].as_f64().unwrap_or_default(),
+                        r["cost"].as_f64().unwrap_or_default(),
+                        r["average_temperature"]
+                            .as_f64()
+                            .map_or("?".to_string(), |t| format!("{t:.1}°C")),
+                        r["degree_days"]
+                            .as_f64()
+                            .map_or("?".to_string(), |d| format!("{d:.1}")),
+                    );
+                }
+            }
+        }
+        Command::Setpoints { from, to, step } => {
+            let mut key = format!("kal/stat/daemon/setpoints?step={step}");
+            if let Some(from) = from {
+                key.push_str(&format!(";from={from}"));
+            }
+            if let Some(to) = to {
+                key.push_str(&format!(";to={to}"));
+            }
+            let setpoints = query(&session, &key).await;
+            if args.json {
+                println!("{setpoints}");
+            } else {
+                let tz = timezone(&session).await;
+                println!(
+                    "setpoint: {:.1}°C",
+                    setpoints["setpoint"].as_f64().unwrap_or_default()
+                );
+                print_next(&setpoints["next"], tz);
+                for point in setpoints["curve"].as_array().into_iter().flatten() {
+                    println!(
+                        "  {} {:.1}°C",
+                        local(&point["time"], tz, "%a %H:%M"),
+                        point["temperature"].as_f64().unwrap_or_default(),
+                    );
+                }
+            }
+        }
+        Command::Plan { hours } => {
+            let key = format!("kal/stat/daemon/plan?hours={hours}");
+            let plans = query(&session, &key).await;
+            if args.json {
+                println!("{plans}");
+            } else {
+                let tz = timezone(&session).await;
+                for (zone, segments) in plans.as_object().into_iter().flatten() {
+                    println!("{zone}:");
+                    for s in segments.as_array().into_iter().flatten() {
+                        let time = |k: &str| local(&s[k], tz, "%a %H:%M");
+                        println!(
+                            "  {} - {} {:3} → {:.1}°C, cost {:.2}",
+                            time("start"),
+                            time("end"),
+                            if s["on"].as_bool() == Some(true) {
+                                "On"
+                            } else {
+                                "Off"
+                            },
+                            s["temperature"].as_f64().unwrap_or_default(),
+                            s["cost"].as_f64().unwrap_or_default(),
+                        );
+                    }
+                }
+            }
+        }
+        Command::Queue(QueueCommand::List) => {
+            let jobs = query(&session, "kal/stat/daemon/queue").await;
+            if args.json {
+                println!("{jobs}");
+            } else {
+                let tz = timezone(&session).await;
+                for job in jobs.as_array().into_iter().flatten() {
+                    println!(
+                        "{} {} {} {}",
+                        job["id"],
+                        local(&job["at"], tz, "%a %F %R"),
+                        job["command"].as_str().unwrap_or("?"),
+                        job["payload"].as_str().unwrap_or("?"),
+                    );
+                }
+            }
+        }
+        Command::Queue(QueueCommand::Add {
+            when,
+            command,
+            payload,
+        }) => {
+            let payload = format!("{when}|{command}|{payload}");
+            put(&session, &args, "kal/cmnd/daemon/at", payload).await;
+        }
+        Command::Queue(QueueCommand::Cancel { id }) => {
+            put(&session, &args, "kal/cmnd/daemon/cancel", id.to_string()).await;
+        }
+        Command::Alarms => {
+            let alarms = query(&session, "kal/stat/daemon/alarms").await;
+            if args.json {
+                println!("{alarms}");
+            } else {
+                let tz = timezone(&session).await;
+                for alarm in alarms.as_array().into_iter().flatten() {
+                    let raised = local(&alarm["raised"], tz, "%F %T");
+                    println!(
+                        "{raised} {} {} {}: {}",
+                        alarm["severity"].as_str().unwrap_or("?"),
+                        alarm["kind"].as_str().unwrap_or("?"),
+                        alarm["subject"].as_str().unwrap_or("?"),
+                        alarm["message"].as_str().unwrap_or("?"),
+                    );
+                }
+            }
+        }
+        Command::Audit { from, to } => {
+            let mut key = "kal/stat/daemon/audit?".to_string();
+            if let Some(from) = from {
+                key.push_str(&format!("from={from};"));
+            }
+            if let Some(to) = to {
+                key.push_str(&format!("to={to};"));
+            }
+            let entries = query(&session, &key).await;
+            if args.json {
+                println!("{entries}");
+            } else {
+                let tz = timezone(&session).await;
+                for e in entries.as_array().into_iter().flatten() {
+                    let time = local(&e["time"], tz, "%F %T");
+                    match e["kind"].as_str() {
+                        Some("command") => println!(
+                            "{time} {} ← {} ({}{})",
+                            e["key"].as_str().unwrap_or("?"),
+                            e["payload"].as_str().unwrap_or("?"),
+                            e["result"].as_str().unwrap_or("?"),
+                            e["source"]
+                                .as_str()
+                                .map_or(String::new(), |s| format!(", from {s}")),
+                        ),
+                        Some("source") => println!(
+                            "{time} {} {} {}: {}",
+                            e["source"].as_str().unwrap_or("?"),
+                            e["relay"].as_str().unwrap_or("?"),
+                            if e["on"].as_bool() == Some(true) {
+                                "On"
+                            } else {
+                                "Off"
+                            },
+                            e["reason"].as_str().unwrap_or("?"),
+                        ),
+                        Some("stage") => println!(
+                            "{time} {} stage {} {}: {}",
+                            e["zone"].as_str().unwrap_or("?"),
+                            e["relay"].as_str().unwrap_or("?"),
+                            if e["on"].as_bool() == Some(true) {
+                                "On"
+                            } else {
+                                "Off"
+                            },
+                            e["reason"].as_str().unwrap_or("?"),
+                        ),
+                        Some(kind @ ("relay" | "cooler")) => println!(
+                            "{time} {} {kind} {}: {}",
+                            e["zone"].as_str().unwrap_or("?"),
+                            if e["on"].as_bool() == Some(true) {
+                                "On"
+                            } else {
+                                "Off"
+                            },
+                            e["reason"].as_str().unwrap_or("?"),
+                        ),
+                        _ => println!("{time} {e}"),
+                    }
+                }
+            }
+        }
+        Command::Watch { key } => {
+            let subscriber = session.declare_subscriber(key).await.unwrap();
+            while let Ok(sample) = subscriber.recv_async().await {
+                let payload = sample
+                    .payload()
+                    .try_to_string()
+                    .map(|p| p.to_string())
+                    .unwrap_or_else(|_| "<binary>".to_string());
+                let now = Local::now();
+                if args.json {
+                    let line = json!({
+                        "time": now,
+                        "key": sample.key_expr().as_str(),
+                        "payload": payload,
+                    });
+                    println!("{line}");
+                } else {
+                    println!("{} {} {payload}", now.format("%T"), sample.key_expr());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall clock time.
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Time starting from the wall clock, but running `speed` times faster.
+#[derive(Debug)]
+pub struct ScaledClock {
+    origin: DateTime<Utc>,
+    started: Instant,
+    speed: f64,
+}
+
+impl ScaledClock {
+    pub fn new(speed: f64) -> Self {
+        Self::starting(Utc::now(), speed)
+    }
+
+    /// Starting from `origin` instead of the wall clock.
+    pub fn starting(origin: DateTime<Utc>, speed: f64) -> Self {
+        Self {
+            origin,
+            started: Instant::now(),
+            speed,
+        }
+    }
+}
+
+impl Clock for ScaledClock {
+    fn now(&self) -> DateTime<Utc> {
+        let elapsed = self.started.elapsed().mul_f64(self.speed);
+        self.origin + TimeDelta::from_std(elapsed).unwrap()
+    }
+}
+
+/// Time only moving when told to, for tests.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Mutex::new(now))
+    }
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+    pub fn advance(&self, delta: TimeDelta) {
+        *self.0.lock().unwrap() += delta;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaled_clock() {
+        let origin = "2025-01-06T06:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let started = Instant::now();
+        let clock = ScaledClock::starting(origin, 3600.0);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let now = clock.now();
+        let elapsed = started.elapsed();
+        // an hour per second
+        assert!(now - origin >= TimeDelta::seconds(72));
+        assert!(now - origin <= TimeDelta::from_std(elapsed.mul_f64(3600.0)).unwrap());
+    }
+}
@@ -0,0 +1,129 @@
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::{
+    AlarmConfig, AnomalyConfig, AuditConfig, AuthConfig, CoolingConfig, EnergyConfig, HeatSource,
+    HomeAssistant, Limits, Location, PresenceConfig, Rule, SolarConfig, TariffConfig, Zone,
+};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("can't read config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("can't parse config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub connect: Vec<String>,
+    pub zones: Vec<Zone>,
+    /// boilers shared by zones
+    pub sources: Vec<HeatSource>,
+    /// named setpoints, in addition to or replacing comfort, eco and frost
+    pub presets: BTreeMap<String, f64>,
+    /// device publishing the outdoor `kal/tele/<outdoor>/temperature`
+    pub outdoor: Option<String>,
+    /// where to keep state across restarts, if anywhere
+    pub state_dir: Option<PathBuf>,
+    #[serde(with = "humantime_serde")]
+    pub device_timeout: Duration,
+    /// run the clock faster than real time, for simulations
+    pub speed: f64,
+    /// for schedule points relative to sunrise and sunset
+    pub location: Option<Location>,
+    /// of schedule points, tariff slots and energy reports, the host's by
+    /// default, eg. `Europe/Paris`
+    pub timezone: Option<Tz>,
+    pub homeassistant: Option<HomeAssistant>,
+    /// serve prometheus metrics on http://<metrics>/metrics
+    pub metrics: Option<SocketAddr>,
+    pub energy: EnergyConfig,
+    pub audit: AuditConfig,
+    /// require signed commands
+    pub auth: Option<AuthConfig>,
+    /// shift heating to off-peak periods
+    pub tariff: Option<TariffConfig>,
+    /// raise setpoints with PV surplus
+    pub solar: Option<SolarConfig>,
+    /// eco setback while nobody is home
+    pub presence: Option<PresenceConfig>,
+    /// for zones with a `cooler`
+    pub cooling: CoolingConfig,
+    /// overheat and frost protection
+    pub limits: Limits,
+    /// stale sensors, relay mismatches and notifications
+    pub alarms: AlarmConfig,
+    /// heating that doesn't warm zones up
+    pub anomaly: AnomalyConfig,
+    /// automations over zenoh keys
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            connect: vec!["tcp/127.0.0.1:7447".to_string()],
+            zones: vec![Zone::new("garage", "tasmota_43D8FD", "garage")],
+            sources: Vec::new(),
+            presets: BTreeMap::new(),
+            outdoor: None,
+            state_dir: None,
+            // devices re-send their state every 5 minutes
+            device_timeout: Duration::from_secs(12 * 60),
+            speed: 1.0,
+            location: None,
+            timezone: None,
+            homeassistant: None,
+            metrics: None,
+            energy: EnergyConfig::default(),
+            audit: AuditConfig::default(),
+            auth: None,
+            tariff: None,
+            solar: None,
+            presence: None,
+            cooling: CoolingConfig::default(),
+            limits: Limits::default(),
+            alarms: AlarmConfig::default(),
+            anomaly: AnomalyConfig::default(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the TOML file pointed to by `KAL_CONFIG`, or use defaults.
+    pub fn load() -> Result<Self, ConfigError> {
+        let config: Self = match std::env::var_os("KAL_CONFIG") {
+            Some(path) => toml::from_str(&std::fs::read_to_string(path)?)?,
+            None => Self::default(),
+        };
+        config.check()?;
+        Ok(config)
+    }
+
+    /// What the types don't enforce.
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if !(self.speed.is_finite() && self.speed > 0.0) {
+            return Err(ConfigError::Invalid(format!(
+                "speed must be positive, not {}",
+                self.speed
+            )));
+        }
+        if let Some(tariff) = &self.tariff {
+            tariff.check().map_err(ConfigError::Invalid)?;
+        }
+        for rule in &self.rules {
+            rule.check().map_err(ConfigError::Invalid)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Mode, Temperature};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoolingConfig {
+    /// cool above this, in °C
+    pub setpoint: f64,
+    /// smallest gap between the heat and cool setpoints, in °C
+    pub deadband: f64,
+}
+
+impl Default for CoolingConfig {
+    fn default() -> Self {
+        Self {
+            setpoint: 26.0,
+            deadband: 2.0,
+        }
+    }
+}
+
+impl CoolingConfig {
+    /// Cool setpoint, kept at least `deadband` above the heat setpoint.
+    pub fn setpoint(&self, cool: Temperature, heat: Temperature) -> Temperature {
+        let min = heat + self.deadband.into();
+        if cool < min { min } else { cool }
+    }
+}
+
+/// What the heater and cooler relays should do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Action {
+    Heat,
+    Cool,
+    Idle,
+}
+
+impl Action {
+    /// Decide from the mode, the temperature and the setpoints, with a reason.
+    /// None when the mode needs a temperature we don't have yet.
+    pub fn decide(
+        mode: Mode,
+        t: Option<Temperature>,
+        heat: Temperature,
+        cool: Temperature,
+    ) -> Option<(Self, String)> {
+        Some(match (mode, t) {
+            (Mode::On, _) => (Self::Heat, "mode On".to_string()),
+            (Mode::Off, _) => (Self::Idle, "mode Off".to_string()),
+            (_, None) => return None,
+            (Mode::Auto | Mode::Heat | Mode::HeatCool, Some(t)) if t < heat => {
+                (Self::Heat, format!("{t} < {heat}"))
+            }
+            (Mode::Cool | Mode::HeatCool, Some(t)) if t > cool => {
+                (Self::Cool, format!("{t} > {cool}"))
+            }
+            (Mode::Auto | Mode::Heat, Some(t)) => (Self::Idle, format!("{t} >= {heat}")),
+            (Mode::Cool, Some(t)) => (Self::Idle, format!("{t} <= {cool}")),
+            (Mode::HeatCool, Some(t)) => (Self::Idle, format!("{heat} <= {t} <= {cool}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooling() {
+        let config = CoolingConfig::default();
+        let heat = 19.0.into();
+        let cool = config.setpoint(26.0.into(), heat);
+        assert_eq!(cool, 26.0.into());
+        assert_eq!(config.setpoint(20.0.into(), heat), 21.0.into());
+
+        let action = |mode, t: f64| Action::decide(mode, Some(t.into()), heat, cool).unwrap().0;
+        assert_eq!(action(Mode::Auto, 18.0), Action::Heat);
+        assert_eq!(action(Mode::Auto, 30.0), Action::Idle);
+        assert_eq!(action(Mode::Cool, 18.0), Action::Idle);
+        assert_eq!(action(Mode::Cool, 27.0), Action::Cool);
+        assert_eq!(action(Mode::HeatCool, 18.0), Action::Heat);
+        assert_eq!(action(Mode::HeatCool, 22.0), Action::Idle);
+        assert_eq!(action(Mode::HeatCool, 27.0), Action::Cool);
+        assert_eq!(action(Mode::Off, 27.0), Action::Idle);
+        assert_eq!(action(Mode::On, 27.0), Action::Heat);
+        assert_eq!(action(Mode::Heat, 18.0), Action::Heat);
+        assert_eq!(action(Mode::Heat, 22.0), Action::Idle);
+        assert_eq!(action(Mode::Heat, 27.0), Action::Idle);
+        assert!(Action::decide(Mode::HeatCool, None, heat, cool).is_none());
+        assert!(Action::decide(Mode::Off, None, heat, cool).is_some());
+    }
+}
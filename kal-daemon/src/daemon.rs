@@ -1,116 +1,1328 @@
-use crate::{Mode, Schedule, Time};
-use log::{debug, info};
+use crate::staging;
+use crate::{
+    Action, Alarm, AlarmKind, Alarms, Anchor, AnomalyConfig, Audit, Auth, AuthError, Calendar,
+    Clock, Config, CoolingConfig, Energy, Event, HeatSource, HomeAssistant, Limits, Metrics, Mode,
+    Output, Presence, Queue, QueueError, Registry, Rule, Rules, ScaledClock, Schedule,
+    ScheduleError, Segment, Setpoint, Solar, SystemClock, Tariff, Temperature, Time, Zone,
+    host_timezone, parse_presence, parse_when,
+};
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+use log::{debug, info, warn};
+use serde_json::json;
+use std::path::PathBuf;
 use std::str::FromStr;
-use zenoh::{Result, Session, handlers::FifoChannelHandler, pubsub::Subscriber, sample::Sample};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::{Interval, interval};
+use zenoh::{
+    Result, Session,
+    handlers::FifoChannelHandler,
+    pubsub::Subscriber,
+    query::{Query, Queryable},
+    sample::Sample,
+};
 
 pub struct Daemon {
+    clock: Arc<dyn Clock>,
     schedule: Schedule,
+    schedule_path: Option<PathBuf>,
     mode: Mode,
+    mode_path: Option<PathBuf>,
+    zones: Vec<Zone>,
+    sources: Vec<HeatSource>,
+    registry: Registry,
     session: Session,
     daemon_sub: Subscriber<FifoChannelHandler<Sample>>,
-    temperature_sub: Subscriber<FifoChannelHandler<Sample>>,
+    tele_sub: Subscriber<FifoChannelHandler<Sample>>,
+    stat_queryable: Queryable<FifoChannelHandler<Query>>,
+    homeassistant: Option<HomeAssistant>,
+    homeassistant_sub: Option<Subscriber<FifoChannelHandler<Sample>>>,
+    published_setpoint: Option<Temperature>,
+    published_cool_setpoint: Option<Temperature>,
+    published_preset: Option<String>,
+    outdoor_sensor: Option<String>,
+    outdoor: Option<Temperature>,
+    energy: Energy,
+    audit: Audit,
+    auth: Option<Auth>,
+    tariff: Tariff,
+    queue: Queue,
+    tariff_sub: Option<Subscriber<FifoChannelHandler<Sample>>>,
+    solar: Option<Solar>,
+    solar_sub: Option<Subscriber<FifoChannelHandler<Sample>>>,
+    presence: Option<Presence>,
+    presence_subs: Vec<Subscriber<FifoChannelHandler<Sample>>>,
+    rules: Rules,
+    rule_subs: Vec<Subscriber<FifoChannelHandler<Sample>>>,
+    cooling: CoolingConfig,
+    limits: Limits,
+    alarms: Alarms,
+    anomaly: AnomalyConfig,
+    cool_setpoint: Temperature,
+    metrics: Arc<Metrics>,
+    connected: bool,
+    tick: Interval,
+}
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("unknown command")]
+    Unknown,
+    #[error("can't parse {0:?}")]
+    Parse(String),
+    #[error("rejected: {0}")]
+    Rejected(AuthError),
+    #[error("{0}")]
+    Schedule(#[from] ScheduleError),
+    #[error("{0}")]
+    Queue(#[from] QueueError),
+}
+
+pub type CommandResult = std::result::Result<(), CommandError>;
+
+/// setpoints in a `kal/stat/daemon/setpoints` reply
+const MAX_SAMPLES: i64 = 10_000;
+
+/// between two of them
+const MAX_STEP: TimeDelta = TimeDelta::weeks(1);
+
+/// from the first to the last of them
+const MAX_RANGE: TimeDelta = TimeDelta::days(366);
+
+/// horizon of a `kal/stat/daemon/plan` reply
+const MAX_PLAN_HOURS: i64 = 7 * 24;
+
+/// RFC 3339 time, or midnight in `tz` of a YYYY-MM-DD date.
+fn parse_time(v: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    match DateTime::<Utc>::from_str(v) {
+        Ok(t) => Some(t),
+        Err(_) => Some(Time::MIN.on(NaiveDate::from_str(v).ok()?, tz)),
+    }
+}
+
+/// Reply to a query with an error, which `kalctl` shows.
+async fn reply_err(query: &Query, error: String) {
+    warn!("{}: {error}", query.key_expr());
+    if let Err(e) = query.reply_err(error).await {
+        warn!("can't reply to {}: {e}", query.key_expr());
+    }
+}
+
+/// Wait on an optional subscriber, forever if there is none.
+async fn recv(sub: &Option<Subscriber<FifoChannelHandler<Sample>>>) -> Result<Sample> {
+    match sub {
+        Some(sub) => sub.recv_async().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait on the first of some subscribers, forever if there are none.
+async fn recv_any(subs: &[Subscriber<FifoChannelHandler<Sample>>]) -> Result<Sample> {
+    if subs.is_empty() {
+        return std::future::pending().await;
+    }
+    futures::future::select_all(subs.iter().map(|s| s.recv_async()))
+        .await
+        .0
 }
 
 impl Daemon {
-    pub async fn init() -> Self {
+    pub async fn init(config: Config) -> Self {
+        let clock: Arc<dyn Clock> = if config.speed == 1.0 {
+            Arc::new(SystemClock)
+        } else {
+            Arc::new(ScaledClock::new(config.speed))
+        };
+        Self::with_clock(config, clock).await
+    }
+    pub async fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Self {
         let mut mode = Mode::default();
-        let mut config = zenoh::Config::default();
-        config
-            .insert_json5("connect/endpoints", "[\"tcp/127.0.0.1:7447\"]")
+        let mut zconfig = zenoh::Config::default();
+        zconfig
+            .insert_json5(
+                "connect/endpoints",
+                &serde_json::to_string(&config.connect).unwrap(),
+            )
             .unwrap();
-        let session = zenoh::open(config).await.unwrap();
-        let replies = session.get("kal/cmnd/daemon/mode").await.unwrap();
-        while let Ok(reply) = replies.recv_async().await {
-            if let Ok(payload) = reply.result().unwrap().payload().try_to_string() {
-                mode = payload.as_ref().into();
-                info!("mode {}", mode);
+        let session = zenoh::open(zconfig).await.unwrap();
+        let mode_path = config.state_dir.as_ref().map(|d| d.join("mode"));
+        if let Some(path) = &mode_path
+            && path.exists()
+        {
+            match std::fs::read_to_string(path) {
+                Ok(saved) => {
+                    mode = saved.trim().into();
+                    info!("mode {mode}");
+                }
+                Err(e) => warn!("can't load mode {}: {e}", path.display()),
+            }
+        } else if config.auth.is_none() {
+            // storages keep unsigned commands too
+            let replies = session.get("kal/cmnd/daemon/mode").await.unwrap();
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(payload) = reply.result().unwrap().payload().try_to_string() {
+                    mode = payload.as_ref().into();
+                    info!("mode {}", mode);
+                }
             }
         }
         let daemon_sub = session
             .declare_subscriber("kal/cmnd/daemon/*")
             .await
             .unwrap();
-        let temperature_sub = session
-            .declare_subscriber("kal/tele/tasmota_43D8FD/temperature")
+        let tele_sub = session.declare_subscriber("kal/tele/*/*").await.unwrap();
+        let stat_queryable = session
+            .declare_queryable("kal/stat/daemon/*")
             .await
             .unwrap();
+        let homeassistant_sub = match &config.homeassistant {
+            Some(ha) => Some(session.declare_subscriber(ha.status_key()).await.unwrap()),
+            None => None,
+        };
+
+        let tariff_sub = match config.tariff.as_ref().and_then(|t| t.key.as_ref()) {
+            Some(key) => Some(session.declare_subscriber(key).await.unwrap()),
+            None => None,
+        };
+        let solar_sub = match &config.solar {
+            Some(solar) => Some(session.declare_subscriber(&solar.key).await.unwrap()),
+            None => None,
+        };
+        let mut presence_subs = Vec::new();
+        for key in config.presence.iter().flat_map(|p| &p.keys) {
+            presence_subs.push(session.declare_subscriber(key).await.unwrap());
+        }
+        let rules = Rules::new(config.rules);
+        let mut rule_subs = Vec::new();
+        for key in rules.keys() {
+            rule_subs.push(session.declare_subscriber(key).await.unwrap());
+        }
+        // a flat price until a calendar is known
+        let flat = Calendar {
+            default: config.energy.tariff,
+            slots: Vec::new(),
+        };
+        let tariff = config.tariff.unwrap_or_default();
+        let calendar = match &tariff.file {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| toml::from_str(&c).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| {
+                    warn!("can't load tariff calendar {}: {e}", path.display());
+                    flat
+                }),
+            None => flat,
+        };
+        let timezone = config.timezone.unwrap_or_else(host_timezone);
+        info!("timezone {timezone}");
+        let tariff = Tariff::new(tariff, calendar, timezone);
 
-        Self {
-            schedule: Schedule::default(),
-            mode: mode,
+        for source in &config.sources {
+            for zone in &source.zones {
+                if !config.zones.iter().any(|z| &z.name == zone) {
+                    warn!("heat source {} serves unknown zone {zone}", source.name);
+                }
+            }
+        }
+        let registry = Registry::new(
+            config
+                .zones
+                .iter()
+                .flat_map(|z| {
+                    let stages = z.stages.iter().map(|s| &s.relay);
+                    [&z.sensor, &z.relay]
+                        .into_iter()
+                        .chain(&z.cooler)
+                        .chain(stages)
+                })
+                .chain(
+                    config
+                        .sources
+                        .iter()
+                        .flat_map(|s| [Some(&s.relay), s.pump.as_ref()])
+                        .flatten(),
+                )
+                .map(|d| d.as_str()),
+            config.device_timeout,
+            clock.now(),
+        );
+        // keep timers in step with an accelerated clock
+        let tick = Duration::from_secs(10).div_f64(config.speed);
+
+        let energy = Energy::new(
+            config.energy,
+            config.state_dir.clone(),
+            timezone,
+            clock.now(),
+        );
+        let audit = Audit::new(config.audit, config.state_dir.clone());
+        let queue = Queue::new(config.state_dir.clone());
+        let metrics = Arc::new(Metrics::default());
+        if let Some(addr) = config.metrics {
+            tokio::spawn(metrics.clone().serve(addr));
+        }
+
+        let schedule_path = config.state_dir.as_ref().map(|d| d.join("schedule.json"));
+        let mut schedule = match &schedule_path {
+            Some(path) if path.exists() => std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| serde_json::from_str(&c).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| {
+                    warn!("can't load schedule {}: {e}", path.display());
+                    Schedule::default()
+                }),
+            // presets from the configuration only seed the first run
+            _ => {
+                let mut schedule = Schedule::default();
+                for (name, temperature) in config.presets {
+                    schedule.set_preset(&name, temperature.into());
+                }
+                schedule
+            }
+        };
+        schedule.set_location(config.location);
+        schedule.set_timezone(timezone);
+
+        let mut daemon = Self {
+            clock,
+            schedule,
+            schedule_path,
+            mode,
+            mode_path,
+            zones: config.zones,
+            sources: config.sources,
+            registry,
             session,
             daemon_sub,
-            temperature_sub,
-        }
+            tele_sub,
+            stat_queryable,
+            homeassistant: config.homeassistant,
+            homeassistant_sub,
+            published_setpoint: None,
+            published_cool_setpoint: None,
+            published_preset: None,
+            outdoor_sensor: config.outdoor,
+            outdoor: None,
+            energy,
+            audit,
+            auth: config.auth.map(Auth::new),
+            tariff,
+            queue,
+            tariff_sub,
+            solar: config.solar.map(Solar::new),
+            solar_sub,
+            presence: config.presence.map(Presence::new),
+            presence_subs,
+            rules,
+            rule_subs,
+            cool_setpoint: config.cooling.setpoint.into(),
+            cooling: config.cooling,
+            limits: config.limits,
+            alarms: Alarms::new(config.alarms),
+            anomaly: config.anomaly,
+            metrics,
+            connected: true,
+            tick: interval(tick),
+        };
+        daemon.publish_discovery().await;
+        daemon.publish_mode().await;
+        daemon
     }
     pub async fn select(&mut self) {
         tokio::select! {
             reply = self.daemon_sub.recv_async() => {
                 self.daemon_rep(reply).await
             }
-            reply = self.temperature_sub.recv_async() => {
-                self.temperature_rep(reply).await
+            reply = self.tele_sub.recv_async() => {
+                self.tele_rep(reply).await
+            }
+            query = self.stat_queryable.recv_async() => {
+                self.stat_rep(query).await
+            }
+            reply = recv(&self.homeassistant_sub) => {
+                self.homeassistant_rep(reply).await
+            }
+            reply = recv(&self.tariff_sub) => {
+                self.tariff_rep(reply).await
+            }
+            reply = recv(&self.solar_sub) => {
+                self.solar_rep(reply).await
+            }
+            reply = recv_any(&self.presence_subs) => {
+                self.presence_rep(reply).await
+            }
+            reply = recv_any(&self.rule_subs) => {
+                self.rule_rep(reply).await
+            }
+            _ = self.tick.tick() => {
+                self.run_queue().await;
+                let fired = self.rules.tick(self.clock.now(), self.schedule.timezone());
+                self.run_rules(fired).await;
+                self.expire_devices().await;
+                self.check_sensors().await;
+                for i in 0..self.zones.len() {
+                    self.check_relay(i).await;
+                }
+                self.poll_solar().await;
+                self.poll_presence().await;
+                for i in 0..self.zones.len() {
+                    self.update_stages(i).await;
+                }
+                self.update_sources().await;
+                self.publish_setpoint().await;
+                self.account().await;
+                self.update_metrics().await;
             }
         }
     }
     async fn daemon_rep(&mut self, reply: Result<Sample>) {
         if let Ok(sample) = reply {
-            match sample.key_expr().as_str() {
-                ke if ke.ends_with("/mode") => {
-                    if let Ok(payload) = sample.payload().try_to_string() {
-                        self.mode = payload.as_ref().into();
-                        info!("mode {}", self.mode);
-                        if self.mode != Mode::Auto {
-                            self.set_relay(self.mode == Mode::On).await;
-                        }
-                    }
+            let key = sample.key_expr().as_str();
+            let command = key.rsplit('/').next().unwrap_or_default();
+            self.metrics
+                .commands
+                .get_or_create(&Metrics::command(command))
+                .inc();
+            let payload = match sample.payload().try_to_string() {
+                Ok(payload) => payload.to_string(),
+                Err(_) => "<binary>".to_string(),
+            };
+            let attachment = sample.attachment().and_then(|a| a.try_to_string().ok());
+            // signatures are timestamped by the wall clock, even in simulation
+            let result = match &mut self.auth {
+                Some(auth) => auth
+                    .verify(key, &payload, attachment.as_deref(), Utc::now())
+                    .map_err(CommandError::Rejected),
+                None => Ok(()),
+            };
+            let result = match result {
+                Ok(()) => self.command(command, &payload).await,
+                Err(e) => Err(e),
+            };
+            self.audit.log(
+                self.clock.now(),
+                Event::Command {
+                    key: key.to_string(),
+                    payload,
+                    source: sample
+                        .source_info()
+                        .map(|s| s.source_id().zid().to_string()),
+                    result: match &result {
+                        Ok(()) => "ok".to_string(),
+                        Err(e) => e.to_string(),
+                    },
+                },
+            );
+            match result {
+                Ok(()) => {
+                    self.save_schedule();
+                    self.control_all().await
                 }
-                ke if ke.ends_with("/insert") => {
-                    if let Ok(payload) = sample.payload().try_to_string() {
-                        let data: Vec<&str> = payload.split("|").collect();
-                        if data.len() == 2 {
-                            if let Ok(time_str) = u32::from_str(data[0]) {
-                                if let Ok(time) = Time::from_minutes(time_str) {
-                                    if let Ok(temperature) = f64::from_str(data[1]) {
-                                        self.schedule.insert(time, temperature.into());
-                                    }
-                                }
-                            }
-                        }
+                Err(e @ CommandError::Rejected(_)) => warn!("{key}: {e}"),
+                Err(e) => {
+                    warn!("{key}: {e}");
+                    self.metrics
+                        .parse_failures
+                        .get_or_create(&Metrics::key(key))
+                        .inc();
+                }
+            }
+        }
+    }
+    async fn command(&mut self, command: &str, payload: &str) -> CommandResult {
+        let parse = || CommandError::Parse(payload.to_string());
+        let temperature = |t: &str| match Setpoint::from_str(t) {
+            Ok(Setpoint::Temperature(t)) => Ok(t),
+            _ => Err(parse()),
+        };
+        match command {
+            "mode" => {
+                self.mode = payload.into();
+                info!("mode {}", self.mode);
+                self.save_mode();
+                self.publish_mode().await;
+            }
+            "override" => {
+                let (setpoint, minutes) = match payload.split_once("|") {
+                    Some((t, m)) => (t, Some(u32::from_str(m).map_err(|_| parse())?)),
+                    None => (payload, None),
+                };
+                // anything else than a temperature or a preset cancels, like HA's "none"
+                match Setpoint::from_str(setpoint) {
+                    Ok(setpoint) if self.schedule.resolve(&setpoint).is_ok() => {
+                        let until =
+                            minutes.map(|m| self.clock.now() + TimeDelta::minutes(m.into()));
+                        self.schedule
+                            .set_override(setpoint, until, self.clock.as_ref())?;
                     }
+                    _ => self.schedule.clear_override(),
+                }
+            }
+            "insert" => {
+                let mut fields = payload.split("|");
+                let (Some(time), Some(setpoint), profile, None) =
+                    (fields.next(), fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(parse());
+                };
+                let time = Anchor::from_str(time)?;
+                self.schedule
+                    .insert(profile, time, Setpoint::from_str(setpoint)?)?;
+            }
+            "remove" => {
+                let (time, profile) = match payload.split_once("|") {
+                    Some((time, profile)) => (time, Some(profile)),
+                    None => (payload, None),
+                };
+                self.schedule.remove(profile, Anchor::from_str(time)?)?;
+            }
+            "cool_setpoint" => {
+                self.cool_setpoint = temperature(payload)?;
+            }
+            "profile" => {
+                self.schedule.activate(payload)?;
+                info!("profile {payload}");
+            }
+            "copy_profile" => {
+                let (from, to) = payload.split_once("|").ok_or_else(parse)?;
+                self.schedule.copy_profile(from, to)?;
+            }
+            "rename_profile" => {
+                let (from, to) = payload.split_once("|").ok_or_else(parse)?;
+                self.schedule.rename_profile(from, to)?;
+            }
+            "delete_profile" => self.schedule.delete_profile(payload)?,
+            "at" => {
+                let mut fields = payload.splitn(3, "|");
+                let (Some(when), Some(command), Some(payload)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(parse());
+                };
+                let now = self.clock.now();
+                let at = parse_when(when, now, self.schedule.timezone())?;
+                let id = self.queue.add(at, command, payload, now)?;
+                info!("job {id}: {command} {payload} at {at}");
+            }
+            "cancel" => {
+                let id = u64::from_str(payload).map_err(|_| parse())?;
+                let job = self.queue.cancel(id)?;
+                info!("job {id} cancelled: {} {}", job.command, job.payload);
+            }
+            "preset" => match payload.split_once("|") {
+                Some((name, t)) => {
+                    let Ok(Setpoint::Preset(name)) = Setpoint::from_str(name) else {
+                        return Err(parse());
+                    };
+                    self.schedule.set_preset(&name, temperature(t)?);
+                    self.publish_discovery().await;
                 }
-                ke if ke.ends_with("/remove") => {
-                    if let Ok(payload) = sample.payload().try_to_string() {
-                        if let Ok(time_str) = u32::from_str(&payload) {
-                            if let Ok(time) = Time::from_minutes(time_str) {
-                                self.schedule.remove(time);
+                None => {
+                    self.schedule.remove_preset(payload)?;
+                    self.publish_discovery().await;
+                }
+            },
+            _ => return Err(CommandError::Unknown),
+        }
+        Ok(())
+    }
+    /// Run the queued commands which are due, like commands received now.
+    async fn run_queue(&mut self) {
+        let jobs = self.queue.due(self.clock.now());
+        if jobs.is_empty() {
+            return;
+        }
+        for job in jobs {
+            let source = format!("job {}", job.id);
+            self.run_command(&job.command, &job.payload, source).await;
+        }
+        self.save_schedule();
+        self.control_all().await;
+    }
+    /// Run a command from within the daemon, audited with its `source`.
+    async fn run_command(&mut self, command: &str, payload: &str, source: String) {
+        let result = self.command(command, payload).await;
+        if let Err(e) = &result {
+            warn!("{source}: {e}");
+        }
+        self.audit.log(
+            self.clock.now(),
+            Event::Command {
+                key: format!("kal/cmnd/daemon/{command}"),
+                payload: payload.to_string(),
+                source: Some(source),
+                result: match result {
+                    Ok(()) => "ok".to_string(),
+                    Err(e) => e.to_string(),
+                },
+            },
+        );
+    }
+    async fn rule_rep(&mut self, reply: Result<Sample>) {
+        if let Ok(sample) = reply {
+            let key = sample.key_expr().as_str();
+            let payload = sample.payload().try_to_string().unwrap_or_default();
+            let fired =
+                self.rules
+                    .sample(key, &payload, self.clock.now(), self.schedule.timezone());
+            self.run_rules(fired).await;
+        }
+    }
+    /// Put the actions of rules which fired, running those on
+    /// `kal/cmnd/daemon/*` directly, so they don't need to be signed.
+    async fn run_rules(&mut self, fired: Vec<Rule>) {
+        if fired.is_empty() {
+            return;
+        }
+        for rule in fired {
+            info!("rule {}", rule.name);
+            for put in rule.actions {
+                let source = format!("rule {}", rule.name);
+                match put.key.strip_prefix("kal/cmnd/daemon/") {
+                    Some(command) => self.run_command(command, &put.payload, source).await,
+                    None => {
+                        let result = match self.session.put(&put.key, &put.payload).await {
+                            Ok(()) => "ok".to_string(),
+                            Err(e) => {
+                                warn!("{source}: can't put on {}: {e}", put.key);
+                                e.to_string()
                             }
-                        }
+                        };
+                        self.audit.log(
+                            self.clock.now(),
+                            Event::Command {
+                                key: put.key,
+                                payload: put.payload,
+                                source: Some(source),
+                                result,
+                            },
+                        );
                     }
                 }
-                _ => unimplemented!(),
             }
         }
+        self.save_schedule();
+        self.control_all().await;
+    }
+    /// Keep profiles and presets in `<state_dir>/schedule.json`.
+    fn save_schedule(&self) {
+        if let Some(path) = &self.schedule_path {
+            let json = serde_json::to_string_pretty(&self.schedule).unwrap();
+            if let Err(e) = std::fs::write(path, json) {
+                warn!("can't save schedule to {}: {e}", path.display());
+            }
+        }
+    }
+    /// Keep the mode in `<state_dir>/mode`, as signed commands can't be
+    /// replayed from storages.
+    fn save_mode(&self) {
+        if let Some(path) = &self.mode_path
+            && let Err(e) = std::fs::write(path, self.mode.as_str())
+        {
+            warn!("can't save mode to {}: {e}", path.display());
+        }
+    }
+    async fn tele_rep(&mut self, reply: Result<Sample>) {
+        if let Ok(sample) = reply
+            && let Some((device, item)) = sample
+                .key_expr()
+                .as_str()
+                .strip_prefix("kal/tele/")
+                .and_then(|s| s.split_once("/"))
+            && device != "daemon"
+        {
+            if self.registry.seen(device, item, self.clock.now()) && self.watches(device) {
+                self.clear_alarm(AlarmKind::Offline, device).await;
+            }
+            match item {
+                "temperature" => self.temperature_rep(device, &sample).await,
+                "relay" => self.relay_rep(device, &sample).await,
+                _ => {}
+            }
+        }
+    }
+    /// A sensor reading, dropped unless finite, as NaN would slip past the
+    /// limits.
+    async fn temperature_rep(&mut self, device: &str, sample: &Sample) {
+        if let Ok(payload) = sample.payload().try_to_string()
+            && let Ok(v) = payload.parse::<f64>()
+            && v.is_finite()
+        {
+            let t = v.into();
+            self.account().await;
+            if self.outdoor_sensor.as_deref() == Some(device) {
+                self.outdoor = Some(t);
+            }
+            for i in 0..self.zones.len() {
+                if self.zones[i].sensor != device {
+                    continue;
+                }
+                debug!("{} received {t}", self.zones[i].name);
+                self.zones[i].temperature = Some(t);
+                self.zones[i].updated = Some(self.clock.now());
+                self.metrics
+                    .temperature
+                    .get_or_create(&Metrics::zone(&self.zones[i].name))
+                    .set(v);
+                self.control(i).await;
+                self.check_heating(i).await;
+            }
+            self.clear_alarm(AlarmKind::Stale, device).await;
+        } else {
+            let key = sample.key_expr().as_str();
+            warn!("{key}: can't parse {:?}", sample.payload().try_to_string());
+            self.metrics
+                .parse_failures
+                .get_or_create(&Metrics::key(key))
+                .inc();
+        }
     }
-    async fn temperature_rep(&mut self, reply: Result<Sample>) {
+    /// State a zone relay reports, to check it follows commands.
+    async fn relay_rep(&mut self, device: &str, sample: &Sample) {
+        let payload = sample.payload().try_to_string().unwrap_or_default();
+        let on = match payload.trim().to_lowercase().as_str() {
+            "on" | "true" | "1" => true,
+            "off" | "false" | "0" => false,
+            _ => {
+                let key = sample.key_expr().as_str();
+                warn!("{key}: can't parse {payload:?}");
+                self.metrics
+                    .parse_failures
+                    .get_or_create(&Metrics::key(key))
+                    .inc();
+                return;
+            }
+        };
+        for i in 0..self.zones.len() {
+            if self.zones[i].relay == device {
+                self.zones[i].reported = Some(on);
+                self.check_relay(i).await;
+            }
+        }
+    }
+    async fn control_all(&mut self) {
+        for i in 0..self.zones.len() {
+            self.control(i).await;
+        }
+    }
+    async fn tariff_rep(&mut self, reply: Result<Sample>) {
         if let Ok(sample) = reply {
-            if let Ok(payload) = sample.payload().try_to_string() {
-                if let Ok(v) = payload.parse::<f64>() {
-                    let t = v.into();
-                    debug!("received {t}");
-                    let h = match self.mode {
-                        Mode::Auto => self.schedule.auto(Time::now(), t),
-                        Mode::On => true,
-                        Mode::Off => false,
-                    };
-                    self.set_relay(h).await;
+            let payload = sample.payload().try_to_string().unwrap_or_default();
+            match serde_json::from_str(&payload) {
+                Ok(calendar) => {
+                    info!("tariff calendar updated");
+                    self.tariff.calendar = calendar;
+                    self.control_all().await;
+                }
+                Err(e) => {
+                    let key = sample.key_expr().as_str();
+                    warn!("{key}: can't parse tariff calendar: {e}");
+                    self.metrics
+                        .parse_failures
+                        .get_or_create(&Metrics::key(key))
+                        .inc();
+                }
+            }
+        }
+    }
+    async fn solar_rep(&mut self, reply: Result<Sample>) {
+        if let Ok(sample) = reply
+            && let Some(solar) = &mut self.solar
+        {
+            match sample.payload().try_to_string().map(|p| p.parse::<f64>()) {
+                Ok(Ok(export)) => {
+                    if solar.update(export, self.clock.now()) {
+                        info!("solar boost {}", solar.state().boost);
+                        self.control_all().await;
+                    }
+                }
+                _ => {
+                    let key = sample.key_expr().as_str();
+                    warn!("{key}: can't parse {:?}", sample.payload().try_to_string());
+                    self.metrics
+                        .parse_failures
+                        .get_or_create(&Metrics::key(key))
+                        .inc();
+                }
+            }
+        }
+    }
+    async fn poll_solar(&mut self) {
+        if let Some(solar) = &mut self.solar
+            && solar.poll(self.clock.now())
+        {
+            info!("solar boost {}", solar.state().boost);
+            self.control_all().await;
+        }
+    }
+    async fn presence_rep(&mut self, reply: Result<Sample>) {
+        if let Ok(sample) = reply
+            && let Some(presence) = &mut self.presence
+        {
+            let key = sample.key_expr().as_str();
+            let payload = sample.payload().try_to_string().unwrap_or_default();
+            match parse_presence(&payload) {
+                Some(present) => {
+                    debug!("{key} present {present}");
+                    if presence.update(key, present, self.clock.now()) {
+                        info!("away {}", presence.away());
+                        self.control_all().await;
+                    }
+                }
+                None => {
+                    warn!("{key}: can't parse {payload:?}");
+                    self.metrics
+                        .parse_failures
+                        .get_or_create(&Metrics::key(key))
+                        .inc();
+                }
+            }
+        }
+    }
+    async fn poll_presence(&mut self) {
+        if let Some(presence) = &mut self.presence
+            && presence.poll(self.clock.now())
+        {
+            info!("away {}", presence.away());
+            self.control_all().await;
+        }
+    }
+    /// Current setpoint in Auto mode, and where it comes from.
+    fn setpoint(&self) -> (Temperature, String) {
+        let now = self.clock.now();
+        if let Some(o) = self.schedule.override_at(now) {
+            return (o.temperature, "override".to_string());
+        }
+        let mut setpoint = self.schedule.setpoint(self.clock.as_ref());
+        let mut from = vec!["schedule".to_string()];
+        if let Some(presence) = &self.presence
+            && presence.away()
+        {
+            let eco = presence.config.eco.into();
+            if eco < setpoint {
+                setpoint = eco;
+                from.push(format!("away {eco}"));
+            }
+        }
+        let offsets = [
+            ("tariff", Some(self.tariff.offset(now))),
+            ("solar", self.solar.as_ref().and_then(|s| s.offset())),
+        ];
+        for (name, offset) in offsets {
+            if let Some(offset) = offset
+                && offset != 0.0
+            {
+                setpoint = setpoint + offset.into();
+                from.push(format!("{name} {offset:+}°C"));
+            }
+        }
+        (setpoint, from.join(" and "))
+    }
+    /// Current cool setpoint, above the setpoint by the deadband at least.
+    fn cool_setpoint(&self) -> Temperature {
+        self.cooling.setpoint(self.cool_setpoint, self.setpoint().0)
+    }
+    async fn control(&mut self, zone: usize) {
+        let (setpoint, from) = self.setpoint();
+        let t = self.zones[zone].temperature;
+        let Some((action, reason)) = Action::decide(self.mode, t, setpoint, self.cool_setpoint())
+        else {
+            return;
+        };
+        // what drives the decision, unlike the reason which has the temperature
+        let (reason, cause) = match (self.mode, action) {
+            (Mode::On | Mode::Off, _) => (reason.clone(), reason),
+            (_, Action::Cool) => (format!("{reason} from cooling"), "cooling".to_string()),
+            _ => (format!("{reason} from {from}"), from),
+        };
+        let (action, reason, cause) = match t.and_then(|t| self.limits.enforce(t, action)) {
+            Some((action, reason)) => (action, reason, "limits".to_string()),
+            None => (action, reason, cause),
+        };
+        self.update_limit(zone).await;
+        let name = self.zones[zone].name.clone();
+        let (heat, cool) = (action == Action::Heat, action == Action::Cool);
+        // only audit decisions, not each reading which confirms one
+        let decision = Some((action, cause));
+        if self.zones[zone].audited != decision {
+            self.zones[zone].audited = decision;
+            self.audit.log(
+                self.clock.now(),
+                Event::Relay {
+                    zone: name.clone(),
+                    on: heat,
+                    reason: reason.clone(),
+                },
+            );
+            if self.zones[zone].cooler.is_some() {
+                self.audit.log(
+                    self.clock.now(),
+                    Event::Cooler {
+                        zone: name,
+                        on: cool,
+                        reason,
+                    },
+                );
+            }
+        }
+        // switch off first, so that both are never on together
+        if heat {
+            self.set_cooler(zone, false).await;
+            self.set_relay(zone, true).await;
+        } else {
+            self.set_relay(zone, false).await;
+            self.set_cooler(zone, cool).await;
+        }
+        self.update_stages(zone).await;
+        self.update_sources().await;
+    }
+    /// Drive heat sources from the demand of the zones they serve.
+    async fn update_sources(&mut self) {
+        let now = self.clock.now();
+        for source in &mut self.sources {
+            let demand = self
+                .zones
+                .iter()
+                .any(|z| z.heating == Some(true) && source.serves(&z.name));
+            for (output, on, reason) in source.update(demand, now) {
+                let Some(key) = source.key(output) else {
+                    continue;
                 };
+                let relay = match output {
+                    Output::Boiler => source.relay.clone(),
+                    Output::Pump => source.pump.clone().unwrap_or_default(),
+                };
+                debug!("{} {relay} {on}", source.name);
+                self.audit.log(
+                    now,
+                    Event::Source {
+                        source: source.name.clone(),
+                        relay,
+                        on,
+                        reason,
+                    },
+                );
+                let p = if on { "On" } else { "Off" };
+                self.session.put(key, p).await.unwrap();
+            }
+        }
+    }
+    /// Whether a zone or a heat source uses a device.
+    fn watches(&self, device: &str) -> bool {
+        self.zones.iter().any(|z| z.uses(device)) || self.sources.iter().any(|s| s.uses(device))
+    }
+    /// Engage or release the extra stages of a zone.
+    async fn update_stages(&mut self, zone: usize) {
+        if self.zones[zone].stages.is_empty() {
+            return;
+        }
+        self.account().await;
+        let now = self.clock.now();
+        let setpoint = f64::from(self.setpoint().0);
+        let zone = &mut self.zones[zone];
+        let error = zone.temperature.map_or(0.0, |t| setpoint - f64::from(t));
+        let demand = zone.heating_since.filter(|_| zone.heating == Some(true));
+        for (i, on, reason) in staging::update(&mut zone.stages, demand, error, now) {
+            let stage = &zone.stages[i];
+            debug!("{} stage {} {on}", zone.name, stage.relay);
+            self.audit.log(
+                now,
+                Event::Stage {
+                    zone: zone.name.clone(),
+                    relay: stage.relay.clone(),
+                    on,
+                    reason,
+                },
+            );
+            let p = if on { "On" } else { "Off" };
+            self.session.put(stage.key(), p).await.unwrap();
+        }
+    }
+    async fn stat_rep(&mut self, query: Result<Query>) {
+        if let Ok(query) = query {
+            let payload = match query.key_expr().as_str() {
+                ke if ke.ends_with("/devices") => self.registry.to_json(),
+                ke if ke.ends_with("/queue") => serde_json::to_string(self.queue.jobs()).unwrap(),
+                ke if ke.ends_with("/alarms") => {
+                    serde_json::to_string(&self.alarms.active().collect::<Vec<_>>()).unwrap()
+                }
+                ke if ke.ends_with("/status") => {
+                    let (time, temperature) = self.schedule.next_change(self.clock.now());
+                    json!({
+                    "mode": self.mode,
+                    "profile": self.schedule.active(),
+                    "timezone": self.schedule.timezone(),
+                    "setpoint": self.setpoint().0,
+                    "next": {"time": time, "temperature": temperature},
+                    "cool_setpoint": self.cool_setpoint(),
+                    "override": self.schedule.current_override(self.clock.as_ref()),
+                    "solar": self.solar.as_ref().map(|s| s.state()),
+                    "presence": self.presence.as_ref().map(|p| p.state()),
+                    "zones": self.zones,
+                    "sources": self.sources,
+                    })
+                    .to_string()
+                }
+                ke if ke.ends_with("/schedule") => {
+                    let profile = query
+                        .parameters()
+                        .get("profile")
+                        .unwrap_or(self.schedule.active());
+                    let points = match self.schedule.points_of(profile) {
+                        Ok(points) => points,
+                        Err(e) => return reply_err(&query, e.to_string()).await,
+                    };
+                    let points: Vec<_> = points
+                        .map(|(time, temperature, setpoint)| match setpoint {
+                            Setpoint::Preset(preset) => json!([time, temperature, preset]),
+                            Setpoint::Temperature(_) => json!([time, temperature]),
+                        })
+                        .collect();
+                    serde_json::to_string(&points).unwrap()
+                }
+                ke if ke.ends_with("/profiles") => json!({
+                    "active": self.schedule.active(),
+                    "profiles": self.schedule.profiles().collect::<Vec<_>>(),
+                })
+                .to_string(),
+                ke if ke.ends_with("/presets") => {
+                    serde_json::to_string(self.schedule.presets()).unwrap()
+                }
+                ke if ke.ends_with("/audit") => {
+                    let tz = self.schedule.timezone();
+                    let time = |k| query.parameters().get(k).and_then(|v| parse_time(v, tz));
+                    let entries = self.audit.query(time("from"), time("to"));
+                    serde_json::to_string(&entries).unwrap()
+                }
+                ke if ke.ends_with("/report") => {
+                    let date = |k| {
+                        query
+                            .parameters()
+                            .get(k)
+                            .and_then(|d| NaiveDate::from_str(d).ok())
+                    };
+                    let reports = self.energy.reports(date("from"), date("to"));
+                    serde_json::to_string(&reports).unwrap()
+                }
+                ke if ke.ends_with("/setpoints") => {
+                    let now = self.clock.now();
+                    let tz = self.schedule.timezone();
+                    let time = |k| query.parameters().get(k).and_then(|v| parse_time(v, tz));
+                    let from = time("from").unwrap_or(now);
+                    let Some(to) = time("to").or(from.checked_add_signed(TimeDelta::days(1)))
+                    else {
+                        return reply_err(&query, format!("no day after {from}")).await;
+                    };
+                    let step = match query.parameters().get("step") {
+                        Some(s) => humantime::parse_duration(s).ok(),
+                        None => Some(std::time::Duration::from_secs(15 * 60)),
+                    };
+                    let Some(step) = step.and_then(|s| TimeDelta::from_std(s).ok()) else {
+                        return reply_err(&query, "invalid step".to_string()).await;
+                    };
+                    let every = humantime::format_duration(step.to_std().unwrap());
+                    if step < TimeDelta::minutes(1) || step > MAX_STEP {
+                        let error = format!("step {every} is not between a minute and a week");
+                        return reply_err(&query, error).await;
+                    }
+                    if to - from > MAX_RANGE {
+                        let error = format!("{from} to {to} is more than a year");
+                        return reply_err(&query, error).await;
+                    }
+                    if (to - from).num_seconds() / step.num_seconds() > MAX_SAMPLES {
+                        let error = format!(
+                            "more than {MAX_SAMPLES} setpoints from {from} to {to} every {every}"
+                        );
+                        return reply_err(&query, error).await;
+                    }
+                    let (time, temperature) = self.schedule.next_change(now);
+                    let curve: Vec<_> = self
+                        .schedule
+                        .curve(from, to, step)
+                        .into_iter()
+                        .map(
+                            |(time, temperature)| json!({"time": time, "temperature": temperature}),
+                        )
+                        .collect();
+                    json!({
+                        "setpoint": self.schedule.setpoint_on(now),
+                        "next": {"time": time, "temperature": temperature},
+                        "curve": curve,
+                    })
+                    .to_string()
+                }
+                ke if ke.ends_with("/plan") => {
+                    let hours = query
+                        .parameters()
+                        .get("hours")
+                        .and_then(|h| h.parse().ok())
+                        .unwrap_or(24);
+                    if !(1..=MAX_PLAN_HOURS).contains(&hours) {
+                        let error =
+                            format!("plans are from 1 to {MAX_PLAN_HOURS} hours, not {hours}");
+                        return reply_err(&query, error).await;
+                    }
+                    let plans: serde_json::Map<_, _> = self
+                        .zones
+                        .iter()
+                        .map(|zone| (zone.name.clone(), json!(self.plan(zone, hours))))
+                        .collect();
+                    serde_json::Value::Object(plans).to_string()
+                }
+                ke => {
+                    warn!("unknown query {ke}");
+                    return;
+                }
             };
+            if let Err(e) = query.reply(query.key_expr().clone(), payload).await {
+                warn!("can't reply to {}: {e}", query.key_expr());
+            }
+        }
+    }
+    /// Relay timeline of a zone for the next hours, from its current state.
+    fn plan(&self, zone: &Zone, hours: i64) -> Vec<Segment> {
+        let mut room = zone.model.clone();
+        if let Some(t) = zone.temperature {
+            room.temperature = t.into();
+        }
+        if let Some(t) = self.outdoor {
+            room.outdoor = t.into();
+        }
+        let horizon = TimeDelta::hours(hours);
+        self.tariff
+            .plan(&self.schedule, room, self.clock.now(), horizon)
+    }
+    async fn homeassistant_rep(&mut self, reply: Result<Sample>) {
+        if let Ok(sample) = reply
+            && sample
+                .payload()
+                .try_to_string()
+                .is_ok_and(|p| p == "online")
+        {
+            self.publish_discovery().await;
+            self.publish_mode().await;
+            self.published_setpoint = None;
+            self.published_cool_setpoint = None;
+            self.published_preset = None;
+            self.publish_setpoint().await;
+        }
+    }
+    async fn publish_discovery(&mut self) {
+        if let Some(ha) = &self.homeassistant {
+            for zone in &self.zones {
+                for (key, config) in ha.discovery(zone, self.schedule.presets().keys()) {
+                    debug!("discovery {key}");
+                    self.session.put(key, config).await.unwrap();
+                }
+            }
+        }
+    }
+    async fn publish_mode(&mut self) {
+        let mode = self.mode.as_str();
+        self.session
+            .put("kal/tele/daemon/mode", mode)
+            .await
+            .unwrap();
+    }
+    async fn publish_setpoint(&mut self) {
+        let setpoint = self.setpoint().0;
+        let preset = self
+            .schedule
+            .current_override(self.clock.as_ref())
+            .and_then(|o| o.preset)
+            .unwrap_or_else(|| "none".to_string());
+        if self.published_preset.as_ref() != Some(&preset) {
+            self.session
+                .put("kal/tele/daemon/preset", &preset)
+                .await
+                .unwrap();
+            self.published_preset = Some(preset);
+        }
+        let cool_setpoint = self.cool_setpoint();
+        if self.published_cool_setpoint != Some(cool_setpoint) {
+            self.published_cool_setpoint = Some(cool_setpoint);
+            let payload = f64::from(cool_setpoint).to_string();
+            self.session
+                .put("kal/tele/daemon/cool_setpoint", payload)
+                .await
+                .unwrap();
+        }
+        if self.published_setpoint != Some(setpoint) {
+            self.published_setpoint = Some(setpoint);
+            let payload = f64::from(setpoint).to_string();
+            self.session
+                .put("kal/tele/daemon/setpoint", payload)
+                .await
+                .unwrap();
+        }
+    }
+    async fn account(&mut self) {
+        let now = self.clock.now();
+        let price = self.tariff.price(now);
+        for report in self.energy.update(now, &self.zones, self.outdoor, price) {
+            let key = format!("kal/tele/daemon/report/{}", report.zone);
+            let payload = serde_json::to_string(&report).unwrap();
+            self.session.put(key, payload).await.unwrap();
+        }
+    }
+    async fn update_metrics(&mut self) {
+        let now = self.clock.now();
+        let setpoint = self.setpoint().0;
+        for zone in &self.zones {
+            let labels = Metrics::zone(&zone.name);
+            self.metrics
+                .setpoint
+                .get_or_create(&labels)
+                .set(setpoint.into());
+            if let Some(updated) = zone.updated {
+                let age = (now - updated).as_seconds_f64();
+                self.metrics.sensor_age.get_or_create(&labels).set(age);
+            }
+        }
+        let info = self.session.info();
+        let connected =
+            info.routers_zid().await.next().is_some() || info.peers_zid().await.next().is_some();
+        if connected && !self.connected {
+            info!("zenoh connected");
+            self.metrics.reconnects.inc();
+        } else if !connected && self.connected {
+            warn!("zenoh disconnected");
+        }
+        self.connected = connected;
+    }
+    async fn expire_devices(&mut self) {
+        for device in self.registry.expire(self.clock.now()) {
+            if self.watches(&device) {
+                let message = format!("{device} went silent");
+                self.raise_alarm(AlarmKind::Offline, &device, message).await;
+            }
+        }
+    }
+    /// Raise an alarm for sensors without a temperature for too long.
+    async fn check_sensors(&mut self) {
+        let now = self.clock.now();
+        let stale = TimeDelta::from_std(self.alarms.config.stale).unwrap();
+        for i in 0..self.zones.len() {
+            if let Some(updated) = self.zones[i].updated
+                && now - updated >= stale
+            {
+                let sensor = self.zones[i].sensor.clone();
+                let message = format!("no temperature from {sensor} since {updated}");
+                self.raise_alarm(AlarmKind::Stale, &sensor, message).await;
+            }
+        }
+    }
+    /// Raise an alarm for zones that don't warm up while heating.
+    async fn check_heating(&mut self, zone: usize) {
+        let now = self.clock.now();
+        let z = &mut self.zones[zone];
+        let heating = z.heating == Some(true);
+        let checked = self.anomaly.check(
+            &mut z.run,
+            heating,
+            z.temperature,
+            &z.model,
+            self.outdoor,
+            now,
+        );
+        let name = z.name.clone();
+        match checked {
+            Some(Ok(())) => self.clear_alarm(AlarmKind::HeatingIneffective, &name).await,
+            Some(Err(message)) => {
+                self.raise_alarm(AlarmKind::HeatingIneffective, &name, message)
+                    .await
+            }
+            None => {}
+        }
+    }
+    /// Track the limit a zone is beyond, with an alarm.
+    async fn update_limit(&mut self, zone: usize) {
+        let t = self.zones[zone].temperature;
+        let limit = t.and_then(|t| self.limits.check(t));
+        let previous = self.zones[zone].limit;
+        if previous == limit {
+            return;
+        }
+        self.zones[zone].limit = limit;
+        let name = self.zones[zone].name.clone();
+        if let Some(previous) = previous {
+            self.clear_alarm(previous.alarm(), &name).await;
+        }
+        if let (Some(limit), Some(t)) = (limit, t) {
+            let message = self.limits.message(limit, t);
+            self.raise_alarm(limit.alarm(), &name, message).await;
+        }
+    }
+    /// Raise an alarm for relays reporting another state than commanded
+    /// for too long.
+    async fn check_relay(&mut self, zone: usize) {
+        let now = self.clock.now();
+        let zone = &mut self.zones[zone];
+        let relay = zone.relay.clone();
+        let (Some(reported), Some(heating)) = (zone.reported, zone.heating) else {
+            return;
+        };
+        if reported == heating {
+            zone.mismatch_since = None;
+            self.clear_alarm(AlarmKind::RelayMismatch, &relay).await;
+            return;
+        }
+        let since = *zone.mismatch_since.get_or_insert(now);
+        if now - since >= TimeDelta::from_std(self.alarms.config.mismatch).unwrap() {
+            let state = |on| if on { "On" } else { "Off" };
+            let message = format!(
+                "{relay} reports {} instead of {}",
+                state(reported),
+                state(heating)
+            );
+            self.raise_alarm(AlarmKind::RelayMismatch, &relay, message)
+                .await;
+        }
+    }
+    async fn raise_alarm(&mut self, kind: AlarmKind, subject: &str, message: String) {
+        if let Some(alarm) = self.alarms.raise(kind, subject, message, self.clock.now()) {
+            warn!("{}: {}", alarm.key(), alarm.message);
+            self.publish_alarm(&alarm).await;
+        }
+    }
+    async fn clear_alarm(&mut self, kind: AlarmKind, subject: &str) {
+        if let Some(alarm) = self.alarms.clear(kind, subject, self.clock.now()) {
+            info!("{} cleared", alarm.key());
+            self.publish_alarm(&alarm).await;
+        }
+    }
+    /// Publish on `kal/alarm/<kind>/<subject>` and notify the sinks.
+    async fn publish_alarm(&self, alarm: &Alarm) {
+        let payload = serde_json::to_string(alarm).unwrap();
+        self.session.put(alarm.key(), payload).await.unwrap();
+        self.alarms.notify(alarm);
+    }
+    async fn set_relay(&mut self, zone: usize, v: bool) {
+        let p = if v { "On" } else { "Off" };
+        if self.zones[zone].heating != Some(v) {
+            self.account().await;
+            self.zones[zone].heating_since = v.then(|| self.clock.now());
+        }
+        let zone = &mut self.zones[zone];
+        debug!("{} relay {p}", zone.name);
+        let labels = Metrics::zone(&zone.name);
+        if zone.heating != Some(v) {
+            self.metrics.relay_switches.get_or_create(&labels).inc();
         }
+        self.metrics.relay.get_or_create(&labels).set(v.into());
+        zone.heating = Some(v);
+        self.session.put(zone.relay_key(), p).await.unwrap();
     }
-    async fn set_relay(&mut self, v: bool) {
+    async fn set_cooler(&mut self, zone: usize, v: bool) {
+        let zone = &mut self.zones[zone];
+        let Some(key) = zone.cooler_key() else {
+            return;
+        };
         let p = if v { "On" } else { "Off" };
-        debug!("relay {p}");
-        self.session.put("kal/cmnd/garage/relay", p).await.unwrap();
+        debug!("{} cooler {p}", zone.name);
+        zone.cooling = Some(v);
+        self.session.put(key, p).await.unwrap();
     }
 }
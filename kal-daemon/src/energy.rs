@@ -0,0 +1,292 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::{Temperature, Time, Zone};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergyConfig {
+    /// heater power, in kW
+    pub heater_power: f64,
+    /// price of a kWh, without a tariff calendar
+    pub tariff: f64,
+    /// base temperature for heating degree-days, in °C
+    pub base_temperature: f64,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            heater_power: 2.0,
+            tariff: 0.2,
+            base_temperature: 18.0,
+        }
+    }
+}
+
+/// Daily summary for a zone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub date: NaiveDate,
+    pub zone: String,
+    pub runtime_hours: f64,
+    /// runtime of each extra stage
+    #[serde(default)]
+    pub stage_runtime_hours: Vec<f64>,
+    pub duty_cycle: f64,
+    pub energy_kwh: f64,
+    pub cost: f64,
+    pub average_temperature: Option<f64>,
+    pub degree_days: Option<f64>,
+}
+
+/// Time integrals over the current day.
+#[derive(Debug, Default, Clone)]
+struct Day {
+    observed: f64,
+    runtime: f64,
+    stages: Vec<f64>,
+    energy: f64,
+    cost: f64,
+    temperature: f64,
+    temperature_observed: f64,
+}
+
+/// Relay runtime accounting, per zone and per day.
+#[derive(Debug)]
+pub struct Energy {
+    config: EnergyConfig,
+    path: Option<PathBuf>,
+    /// where days start and end
+    timezone: Tz,
+    date: NaiveDate,
+    last: DateTime<Utc>,
+    zones: BTreeMap<String, Day>,
+    outdoor: Day,
+    reports: Vec<Report>,
+}
+
+fn local_date(t: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    t.with_timezone(&tz).date_naive()
+}
+
+fn midnight_after(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    Time::MAX.on(date, tz)
+}
+
+impl Energy {
+    /// Past reports are read from and appended to `<state_dir>/reports.jsonl`.
+    pub fn new(
+        config: EnergyConfig,
+        state_dir: Option<PathBuf>,
+        timezone: Tz,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let path = state_dir.map(|d| d.join("reports.jsonl"));
+        let mut reports = Vec::new();
+        if let Some(path) = &path
+            && let Ok(content) = std::fs::read_to_string(path)
+        {
+            for line in content.lines() {
+                match serde_json::from_str(line) {
+                    Ok(report) => reports.push(report),
+                    Err(e) => warn!("{}: {e}", path.display()),
+                }
+            }
+        }
+        Self {
+            config,
+            path,
+            timezone,
+            date: local_date(now, timezone),
+            last: now,
+            zones: BTreeMap::new(),
+            outdoor: Day::default(),
+            reports,
+        }
+    }
+
+    /// Account for the time since the last update, with the state the zones
+    /// and the price of a kWh had during it. Return the reports of the days
+    /// which just ended.
+    pub fn update(
+        &mut self,
+        now: DateTime<Utc>,
+        zones: &[Zone],
+        outdoor: Option<Temperature>,
+        price: f64,
+    ) -> Vec<Report> {
+        let mut ended = Vec::new();
+        while now > self.last {
+            let midnight = midnight_after(self.date, self.timezone);
+            let end = now.min(midnight);
+            let dt = (end - self.last).as_seconds_f64();
+            for zone in zones {
+                let day = self.zones.entry(zone.name.clone()).or_default();
+                day.observed += dt;
+                let mut power = 0.0;
+                if zone.heating == Some(true) {
+                    day.runtime += dt;
+                    power += self.config.heater_power;
+                }
+                day.stages
+                    .resize(zone.stages.len().max(day.stages.len()), 0.0);
+                for (i, stage) in zone.stages.iter().enumerate() {
+                    if stage.is_on() {
+                        day.stages[i] += dt;
+                        power += stage.power;
+                    }
+                }
+                day.energy += dt / 3600.0 * power;
+                day.cost += dt / 3600.0 * power * price;
+                if let Some(t) = zone.temperature {
+                    day.temperature += f64::from(t) * dt;
+                    day.temperature_observed += dt;
+                }
+            }
+            self.outdoor.observed += dt;
+            if let Some(t) = outdoor {
+                self.outdoor.temperature += f64::from(t) * dt;
+                self.outdoor.temperature_observed += dt;
+            }
+            self.last = end;
+            if end == midnight {
+                ended.extend(self.close_day());
+                self.date = local_date(midnight, self.timezone);
+            }
+        }
+        ended
+    }
+
+    fn report(&self, zone: &str, day: &Day) -> Report {
+        let runtime_hours = day.runtime / 3600.0;
+        let average = |d: &Day| {
+            (d.temperature_observed > 0.0).then(|| d.temperature / d.temperature_observed)
+        };
+        Report {
+            date: self.date,
+            zone: zone.to_string(),
+            runtime_hours,
+            stage_runtime_hours: day.stages.iter().map(|s| s / 3600.0).collect(),
+            duty_cycle: if day.observed > 0.0 {
+                day.runtime / day.observed
+            } else {
+                0.0
+            },
+            energy_kwh: day.energy,
+            cost: day.cost,
+            average_temperature: average(day),
+            degree_days: average(&self.outdoor).map(|t| {
+                (self.config.base_temperature - t).max(0.0) * self.outdoor.observed / 86400.0
+            }),
+        }
+    }
+
+    fn close_day(&mut self) -> Vec<Report> {
+        let reports: Vec<_> = self
+            .zones
+            .iter()
+            .map(|(zone, day)| self.report(zone, day))
+            .collect();
+        self.zones.clear();
+        self.outdoor = Day::default();
+        for report in &reports {
+            info!("{report:?}");
+            if let Some(path) = &self.path
+                && let Err(e) = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut f| writeln!(f, "{}", serde_json::to_string(report).unwrap()))
+            {
+                warn!("can't save report to {}: {e}", path.display());
+            }
+        }
+        self.reports.extend(reports.iter().cloned());
+        reports
+    }
+
+    /// Reports between two dates included, with the current day so far.
+    pub fn reports(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Vec<Report> {
+        let today = self.zones.iter().map(|(zone, day)| self.report(zone, day));
+        self.reports
+            .iter()
+            .cloned()
+            .chain(today)
+            .filter(|r| from.is_none_or(|from| from <= r.date) && to.is_none_or(|to| r.date <= to))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Stage;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_energy() {
+        let date = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
+        let t0 = midnight_after(date, Tz::UTC) - TimeDelta::hours(12);
+        let mut zone = Zone::new("garage", "sensor", "relay");
+        let mut energy = Energy::new(EnergyConfig::default(), None, Tz::UTC, t0);
+
+        // 6h on at 20°C, outdoor 8°C
+        zone.heating = Some(true);
+        zone.temperature = Some(20.0.into());
+        let outdoor = Some(8.0.into());
+        assert!(
+            energy
+                .update(t0 + TimeDelta::hours(6), &[zone.clone()], outdoor, 0.2)
+                .is_empty()
+        );
+
+        // 6h off at 16°C, and 2h into the next day
+        zone.heating = Some(false);
+        zone.temperature = Some(16.0.into());
+        let reports = energy.update(t0 + TimeDelta::hours(14), &[zone.clone()], outdoor, 0.2);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.date, date);
+        assert_eq!(report.runtime_hours, 6.0);
+        assert_eq!(report.duty_cycle, 0.5);
+        assert_eq!(report.energy_kwh, 12.0);
+        assert!((report.cost - 2.4).abs() < 1e-9);
+        assert_eq!(report.average_temperature, Some(18.0));
+        // half a day observed, 10°C below base
+        assert_eq!(report.degree_days, Some(5.0));
+
+        let next = energy.reports(date.succ_opt(), None);
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].runtime_hours, 0.0);
+        assert_eq!(energy.reports(None, None).len(), 2);
+    }
+
+    #[test]
+    fn test_stage_energy() {
+        let date = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
+        let t0 = midnight_after(date, Tz::UTC) - TimeDelta::hours(12);
+        let mut zone = Zone::new("garage", "sensor", "relay");
+        let mut stage = Stage::new("resistance");
+        stage.power = 3.0;
+        zone.stages = vec![stage];
+        let mut energy = Energy::new(EnergyConfig::default(), None, Tz::UTC, t0);
+
+        // 4h with both stages, 2h with the zone relay only
+        zone.heating = Some(true);
+        zone.stages[0].on = Some(true);
+        energy.update(t0 + TimeDelta::hours(4), &[zone.clone()], None, 0.2);
+        zone.stages[0].on = Some(false);
+        energy.update(t0 + TimeDelta::hours(6), &[zone.clone()], None, 0.2);
+
+        let report = &energy.reports(None, None)[0];
+        assert_eq!(report.runtime_hours, 6.0);
+        assert_eq!(report.stage_runtime_hours, [4.0]);
+        assert!((report.energy_kwh - 24.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Boiler shared by some zones, with its circulation pump.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeatSource {
+    pub name: String,
+    /// device subscribed to `kal/cmnd/<relay>/relay`, for the boiler
+    pub relay: String,
+    /// device subscribed to `kal/cmnd/<pump>/relay`, for the circulation pump
+    #[serde(default)]
+    pub pump: Option<String>,
+    /// zones heated by this source, every zone if empty
+    #[serde(default)]
+    pub zones: Vec<String>,
+    /// shortest boiler run
+    #[serde(default = "default_min_run", with = "humantime_serde")]
+    pub min_run: Duration,
+    /// how long the pump keeps running after the boiler stops
+    #[serde(default = "default_overrun", with = "humantime_serde")]
+    pub overrun: Duration,
+    /// last boiler command
+    #[serde(skip_deserializing)]
+    pub on: Option<bool>,
+    /// last pump command
+    #[serde(skip_deserializing)]
+    pub pumping: Option<bool>,
+    /// since when the boiler is on
+    #[serde(skip)]
+    since: Option<DateTime<Utc>>,
+    /// when the boiler last stopped
+    #[serde(skip)]
+    stopped: Option<DateTime<Utc>>,
+}
+
+fn default_min_run() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_overrun() -> Duration {
+    Duration::from_secs(3 * 60)
+}
+
+/// Output of a heat source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Boiler,
+    Pump,
+}
+
+impl HeatSource {
+    pub fn new(name: &str, relay: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            relay: relay.to_string(),
+            pump: None,
+            zones: Vec::new(),
+            min_run: default_min_run(),
+            overrun: default_overrun(),
+            on: None,
+            pumping: None,
+            since: None,
+            stopped: None,
+        }
+    }
+
+    pub fn serves(&self, zone: &str) -> bool {
+        self.zones.is_empty() || self.zones.iter().any(|z| z == zone)
+    }
+    pub fn uses(&self, device: &str) -> bool {
+        self.relay == device || self.pump.as_deref() == Some(device)
+    }
+    pub fn key(&self, output: Output) -> Option<String> {
+        let device = match output {
+            Output::Boiler => &self.relay,
+            Output::Pump => self.pump.as_ref()?,
+        };
+        Some(format!("kal/cmnd/{device}/relay"))
+    }
+
+    /// Follow the demand of the zones, keeping the boiler on for `min_run`
+    /// and the pump for `overrun` after it. Return the outputs to switch.
+    pub fn update(&mut self, demand: bool, now: DateTime<Utc>) -> Vec<(Output, bool, String)> {
+        let within = |t: Option<DateTime<Utc>>, d| {
+            t.is_some_and(|t| now - t < TimeDelta::from_std(d).unwrap())
+        };
+        let (boiler, reason) = match demand {
+            true => (true, "zone demand".to_string()),
+            false if within(self.since, self.min_run) => (true, "minimum run".to_string()),
+            false => (false, "no demand".to_string()),
+        };
+        let mut changes = Vec::new();
+        if self.on != Some(boiler) {
+            if self.on == Some(true) {
+                self.stopped = Some(now);
+            }
+            self.on = Some(boiler);
+            self.since = boiler.then_some(now);
+            changes.push((Output::Boiler, boiler, reason));
+        }
+        let pump = boiler || within(self.stopped, self.overrun);
+        if self.pump.is_some() && self.pumping != Some(pump) {
+            self.pumping = Some(pump);
+            let reason = match pump {
+                true => "boiler on".to_string(),
+                false => format!("overrun {}", humantime::format_duration(self.overrun)),
+            };
+            changes.push((Output::Pump, pump, reason));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heat_source() {
+        let t0 = Utc::now();
+        let at = |m| t0 + TimeDelta::minutes(m);
+        let mut source = HeatSource {
+            pump: Some("pump".to_string()),
+            zones: vec!["kitchen".to_string(), "bedroom".to_string()],
+            ..HeatSource::new("boiler", "boiler")
+        };
+        assert!(source.serves("kitchen"));
+        assert!(!source.serves("garage"));
+        let outputs = |changes: Vec<(Output, bool, String)>| {
+            changes
+                .into_iter()
+                .map(|(o, on, _)| (o, on))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            outputs(source.update(false, at(0))),
+            [(Output::Boiler, false), (Output::Pump, false)]
+        );
+        assert_eq!(
+            outputs(source.update(true, at(1))),
+            [(Output::Boiler, true), (Output::Pump, true)]
+        );
+
+        // a short demand still runs the boiler for min_run
+        assert!(source.update(false, at(2)).is_empty());
+        assert_eq!(
+            outputs(source.update(false, at(6))),
+            [(Output::Boiler, false)]
+        );
+
+        // and the pump for the overrun after it
+        assert!(source.update(false, at(8)).is_empty());
+        assert_eq!(
+            outputs(source.update(false, at(9))),
+            [(Output::Pump, false)]
+        );
+        assert!(source.update(false, at(60)).is_empty());
+    }
+}
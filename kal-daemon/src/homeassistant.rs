@@ -0,0 +1,143 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::Zone;
+
+/// Home Assistant MQTT discovery, through the zenoh MQTT plugin.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HomeAssistant {
+    pub prefix: String,
+}
+
+impl Default for HomeAssistant {
+    fn default() -> Self {
+        Self {
+            prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+impl HomeAssistant {
+    /// Home Assistant publishes "online" there when it (re)starts.
+    pub fn status_key(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    /// Discovery topics and payloads for a climate entity and its sensors.
+    pub fn discovery<'a>(
+        &self,
+        zone: &Zone,
+        presets: impl Iterator<Item = &'a String>,
+    ) -> Vec<(String, String)> {
+        let id = format!("kal_{}", zone.name);
+        let device = json!({
+            "identifiers": [id],
+            "name": format!("kal {}", zone.name),
+            "manufacturer": "kal",
+            "model": "kal-daemon",
+        });
+        let mut climate = json!({
+            "name": null,
+            "unique_id": format!("{id}_climate"),
+            "device": device,
+            "modes": ["auto", "heat", "off"],
+            "mode_command_topic": "kal/cmnd/daemon/mode",
+            "mode_state_topic": "kal/tele/daemon/mode",
+            "mode_state_template": "{{ {'Auto': 'auto', 'Heat': 'heat', 'On': 'heat', 'Off': 'off'}[value] }}",
+            "temperature_command_topic": "kal/cmnd/daemon/override",
+            "temperature_state_topic": "kal/tele/daemon/setpoint",
+            "current_temperature_topic": format!("kal/tele/{}/temperature", zone.sensor),
+            "action_topic": format!("kal/tele/{}/relay", zone.relay),
+            "action_template": "{{ 'heating' if value == 'true' else 'idle' }}",
+            "preset_modes": presets.collect::<Vec<_>>(),
+            "preset_mode_command_topic": "kal/cmnd/daemon/override",
+            "preset_mode_state_topic": "kal/tele/daemon/preset",
+            "temperature_unit": "C",
+            "temp_step": 0.5,
+            "precision": 0.1,
+        });
+        if zone.cooler.is_some() {
+            climate["modes"] = json!(["auto", "heat", "cool", "heat_cool", "off"]);
+            climate["mode_state_template"] = json!(
+                "{{ {'Auto': 'auto', 'Heat': 'heat', 'On': 'heat', 'Cool': 'cool', 'HeatCool': 'heat_cool', 'Off': 'off'}[value] }}"
+            );
+            climate["temperature_high_command_topic"] = json!("kal/cmnd/daemon/cool_setpoint");
+            climate["temperature_high_state_topic"] = json!("kal/tele/daemon/cool_setpoint");
+            climate["temperature_low_command_topic"] = json!("kal/cmnd/daemon/override");
+            climate["temperature_low_state_topic"] = json!("kal/tele/daemon/setpoint");
+        }
+        let sensor = |item: &str, class: &str, unit: &str| {
+            json!({
+                "name": item,
+                "unique_id": format!("{id}_{item}"),
+                "device": device,
+                "state_topic": format!("kal/tele/{}/{item}", zone.sensor),
+                "device_class": class,
+                "unit_of_measurement": unit,
+                "state_class": "measurement",
+            })
+        };
+        let relay = json!({
+            "name": "relay",
+            "unique_id": format!("{id}_relay"),
+            "device": device,
+            "state_topic": format!("kal/tele/{}/relay", zone.relay),
+            "device_class": "heat",
+            "payload_on": "true",
+            "payload_off": "false",
+        });
+        [
+            ("climate", "climate", climate),
+            (
+                "sensor",
+                "temperature",
+                sensor("temperature", "temperature", "°C"),
+            ),
+            ("sensor", "humidity", sensor("humidity", "humidity", "%")),
+            ("binary_sensor", "relay", relay),
+        ]
+        .into_iter()
+        .map(|(component, object, config): (&str, &str, Value)| {
+            (
+                format!("{}/{component}/{id}/{object}/config", self.prefix),
+                config.to_string(),
+            )
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    #[test]
+    fn test_discovery() {
+        let zone = Zone::new("garage", "tasmota_43D8FD", "garage");
+        let presets = crate::schedule::default_presets();
+        let configs = HomeAssistant::default().discovery(&zone, presets.keys());
+        let (topic, config) = &configs[0];
+        assert_eq!(topic, "homeassistant/climate/kal_garage/climate/config");
+        let config: Value = serde_json::from_str(config).unwrap();
+        assert_eq!(
+            config["current_temperature_topic"],
+            "kal/tele/tasmota_43D8FD/temperature"
+        );
+        assert_eq!(config["action_topic"], "kal/tele/garage/relay");
+        assert_eq!(config["preset_modes"], json!(["comfort", "eco", "frost"]));
+        // "heat" follows the setpoint, forced On isn't offered
+        assert_eq!(config["modes"], json!(["auto", "heat", "off"]));
+        assert_eq!(Mode::from("heat"), Mode::Heat);
+        assert_eq!(configs.len(), 4);
+
+        let zone = Zone {
+            cooler: Some("ac".to_string()),
+            ..zone
+        };
+        let configs = HomeAssistant::default().discovery(&zone, presets.keys());
+        let config: Value = serde_json::from_str(&configs[0].1).unwrap();
+        assert_eq!(config["modes"][3], "heat_cool");
+    }
+}
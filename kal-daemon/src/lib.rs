@@ -1,11 +1,55 @@
+mod alarm;
+mod anomaly;
+mod audit;
+mod auth;
+mod clock;
+mod config;
+mod cooling;
 mod daemon;
+mod energy;
+mod heat_source;
+mod homeassistant;
+mod limits;
+mod metrics;
 mod mode;
+mod presence;
+mod queue;
+mod registry;
+mod room;
+mod rules;
 mod schedule;
+mod solar;
+mod staging;
+mod sun;
+mod tariff;
 mod temperature;
 mod time;
+mod zone;
 
-pub use crate::daemon::Daemon;
+pub use crate::alarm::{Alarm, AlarmConfig, AlarmKind, Alarms, Severity, Sink, SinkError};
+pub use crate::anomaly::{AnomalyConfig, Run};
+pub use crate::audit::{Audit, AuditConfig, Entry, Event};
+pub use crate::auth::{Auth, AuthConfig, AuthError, sign};
+pub use crate::clock::{Clock, ManualClock, ScaledClock, SystemClock};
+pub use crate::config::{Config, ConfigError};
+pub use crate::cooling::{Action, CoolingConfig};
+pub use crate::daemon::{CommandError, CommandResult, Daemon};
+pub use crate::energy::{Energy, EnergyConfig, Report};
+pub use crate::heat_source::{HeatSource, Output};
+pub use crate::homeassistant::HomeAssistant;
+pub use crate::limits::{Limit, Limits};
+pub use crate::metrics::Metrics;
 pub use crate::mode::Mode;
-pub use crate::schedule::Schedule;
+pub use crate::presence::{Presence, PresenceConfig, PresenceState, parse_presence};
+pub use crate::queue::{Job, Queue, QueueError, parse_when};
+pub use crate::registry::{Device, Liveness, Registry};
+pub use crate::room::Room;
+pub use crate::rules::{Condition, Put, Rule, Rules, Trigger};
+pub use crate::schedule::{Anchor, Override, Schedule, ScheduleError, Setpoint};
+pub use crate::solar::{Solar, SolarConfig, SolarState};
+pub use crate::staging::Stage;
+pub use crate::sun::Location;
+pub use crate::tariff::{Calendar, Segment, Slot, Tariff, TariffConfig};
 pub use crate::temperature::Temperature;
-pub use crate::time::Time;
+pub use crate::time::{Time, host_timezone};
+pub use crate::zone::Zone;
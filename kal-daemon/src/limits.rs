@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Action, AlarmKind, Temperature};
+
+/// Hard limits, enforced whatever the mode or override.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// never heat above this, in °C
+    pub max: f64,
+    /// always heat below this, in °C
+    pub frost: f64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max: 30.0,
+            frost: 5.0,
+        }
+    }
+}
+
+/// Limit a zone is beyond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Limit {
+    Overheat,
+    Frost,
+}
+
+impl Limit {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Overheat => "overheat",
+            Self::Frost => "frost",
+        }
+    }
+    pub fn alarm(self) -> AlarmKind {
+        match self {
+            Self::Overheat => AlarmKind::Overheat,
+            Self::Frost => AlarmKind::Frost,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check(&self, t: Temperature) -> Option<Limit> {
+        if t > self.max.into() {
+            Some(Limit::Overheat)
+        } else if t < self.frost.into() {
+            Some(Limit::Frost)
+        } else {
+            None
+        }
+    }
+
+    pub fn message(&self, limit: Limit, t: Temperature) -> String {
+        match limit {
+            Limit::Overheat => format!("{t} > max {}", Temperature::from(self.max)),
+            Limit::Frost => format!("{t} < frost {}", Temperature::from(self.frost)),
+        }
+    }
+
+    /// Replace the action decided for a zone at `t` when it's beyond a limit,
+    /// with the reason.
+    pub fn enforce(&self, t: Temperature, action: Action) -> Option<(Action, String)> {
+        let limit = self.check(t)?;
+        let action = match (limit, action) {
+            (Limit::Overheat, Action::Heat) => Action::Idle,
+            (Limit::Frost, Action::Cool | Action::Idle) => Action::Heat,
+            _ => return None,
+        };
+        Some((action, self.message(limit, t)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits::default();
+        assert_eq!(limits.check(20.0.into()), None);
+        assert_eq!(limits.check(31.0.into()), Some(Limit::Overheat));
+        assert_eq!(limits.check(4.0.into()), Some(Limit::Frost));
+
+        let action = |t: f64, action| limits.enforce(t.into(), action).map(|(a, _)| a);
+        assert_eq!(action(20.0, Action::Heat), None);
+        assert_eq!(action(31.0, Action::Heat), Some(Action::Idle));
+        assert_eq!(action(31.0, Action::Cool), None);
+        assert_eq!(action(4.0, Action::Idle), Some(Action::Heat));
+        assert_eq!(action(4.0, Action::Cool), Some(Action::Heat));
+        assert_eq!(action(4.0, Action::Heat), None);
+    }
+}
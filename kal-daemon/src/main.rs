@@ -1,9 +1,10 @@
-use kal_daemon::Daemon;
+use kal_daemon::{Config, Daemon};
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let mut daemon = Daemon::init().await;
+    let config = Config::load().expect("invalid configuration");
+    let mut daemon = Daemon::init(config).await;
     loop {
         daemon.select().await;
     }
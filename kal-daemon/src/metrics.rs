@@ -0,0 +1,160 @@
+use axum::{Router, http::header::CONTENT_TYPE, routing::get};
+use log::{error, info};
+use prometheus_client::encoding::{EncodeLabelSet, text::encode};
+use prometheus_client::metrics::{counter::Counter, family::Family, gauge::Gauge};
+use prometheus_client::registry::Registry;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ZoneLabels {
+    pub zone: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct CommandLabels {
+    pub command: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct KeyLabels {
+    pub key: String,
+}
+
+const OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+type FloatGauge = Gauge<f64, AtomicU64>;
+
+/// Prometheus metrics, served on `/metrics` if configured.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    pub temperature: Family<ZoneLabels, FloatGauge>,
+    pub setpoint: Family<ZoneLabels, FloatGauge>,
+    pub relay: Family<ZoneLabels, Gauge>,
+    pub relay_switches: Family<ZoneLabels, Counter>,
+    pub sensor_age: Family<ZoneLabels, FloatGauge>,
+    pub commands: Family<CommandLabels, Counter>,
+    pub parse_failures: Family<KeyLabels, Counter>,
+    pub reconnects: Counter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let mut registry = Registry::with_prefix("kal");
+        let metrics = Self {
+            temperature: Default::default(),
+            setpoint: Default::default(),
+            relay: Default::default(),
+            relay_switches: Default::default(),
+            sensor_age: Default::default(),
+            commands: Default::default(),
+            parse_failures: Default::default(),
+            reconnects: Default::default(),
+            registry: Registry::default(),
+        };
+        registry.register(
+            "temperature_celsius",
+            "Last temperature reading",
+            metrics.temperature.clone(),
+        );
+        registry.register(
+            "setpoint_celsius",
+            "Current setpoint",
+            metrics.setpoint.clone(),
+        );
+        registry.register("relay", "Last relay command", metrics.relay.clone());
+        registry.register(
+            "relay_switches",
+            "Relay state changes",
+            metrics.relay_switches.clone(),
+        );
+        registry.register(
+            "sensor_age_seconds",
+            "Time since the last temperature reading",
+            metrics.sensor_age.clone(),
+        );
+        registry.register(
+            "commands",
+            "Commands received on kal/cmnd/daemon/*",
+            metrics.commands.clone(),
+        );
+        registry.register(
+            "parse_failures",
+            "Commands and readings which could not be parsed",
+            metrics.parse_failures.clone(),
+        );
+        registry.register(
+            "zenoh_reconnects",
+            "Zenoh sessions regained",
+            metrics.reconnects.clone(),
+        );
+        Self {
+            registry,
+            ..metrics
+        }
+    }
+}
+
+impl Metrics {
+    pub fn zone(zone: &str) -> ZoneLabels {
+        ZoneLabels {
+            zone: zone.to_string(),
+        }
+    }
+    pub fn command(command: &str) -> CommandLabels {
+        CommandLabels {
+            command: command.to_string(),
+        }
+    }
+    pub fn key(key: &str) -> KeyLabels {
+        KeyLabels {
+            key: key.to_string(),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        encode(&mut out, &self.registry).unwrap();
+        out
+    }
+
+    pub async fn serve(self: Arc<Self>, addr: SocketAddr) {
+        let app = Router::new().route(
+            "/metrics",
+            get(async move || ([(CONTENT_TYPE, OPENMETRICS)], self.render())),
+        );
+        match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => {
+                info!("metrics on http://{addr}/metrics");
+                if let Err(e) = axum::serve(listener, app).await {
+                    error!("metrics server: {e}");
+                }
+            }
+            Err(e) => error!("can't bind metrics on {addr}: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics
+            .temperature
+            .get_or_create(&Metrics::zone("garage"))
+            .set(17.5);
+        metrics
+            .commands
+            .get_or_create(&Metrics::command("mode"))
+            .inc();
+        let out = metrics.render();
+        assert!(out.contains("kal_temperature_celsius{zone=\"garage\"} 17.5"));
+        assert!(out.contains("kal_commands_total{command=\"mode\"} 1"));
+        assert!(out.ends_with("# EOF\n"));
+    }
+}
@@ -1,14 +1,16 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize)]
 pub enum Mode {
+    #[default]
     Auto,
+    /// heat regardless of the setpoint
     On,
     Off,
-}
-
-impl Default for Mode {
-    fn default() -> Self {
-        Self::Auto
-    }
+    /// heat below the setpoint, never cool
+    Heat,
+    /// cool above the cool setpoint
+    Cool,
+    /// heat below the setpoint, cool above the cool setpoint
+    HeatCool,
 }
 
 impl From<Option<bool>> for Mode {
@@ -25,7 +27,10 @@ impl From<&str> for Mode {
     fn from(value: &str) -> Self {
         match value {
             "ON" | "On" | "on" | "TRUE" | "True" | "true" | "1" => Self::On,
+            "HEAT" | "Heat" | "heat" => Self::Heat,
             "OFF" | "Off" | "off" | "FALSE" | "False" | "false" | "0" => Self::Off,
+            "COOL" | "Cool" | "cool" => Self::Cool,
+            "HEATCOOL" | "HeatCool" | "heatcool" | "heat_cool" => Self::HeatCool,
             _ => Self::Auto,
         }
     }
@@ -37,6 +42,9 @@ impl Mode {
             Self::On => "On",
             Self::Off => "Off",
             Self::Auto => "Auto",
+            Self::Heat => "Heat",
+            Self::Cool => "Cool",
+            Self::HeatCool => "HeatCool",
         }
     }
 }
@@ -0,0 +1,149 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresenceConfig {
+    /// key expressions of presence sources: phones, PIR sensors, HA persons…
+    pub keys: Vec<String>,
+    /// scheduled setpoints are capped to this while nobody is home, in °C
+    #[serde(default = "default_eco")]
+    pub eco: f64,
+    /// how long every source must be absent before the setback
+    #[serde(default = "default_away_delay", with = "humantime_serde")]
+    pub away_delay: Duration,
+    /// how long someone must be present before restoring comfort
+    #[serde(default, with = "humantime_serde")]
+    pub arrival_delay: Duration,
+}
+
+fn default_eco() -> f64 {
+    16.0
+}
+
+fn default_away_delay() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+/// Read a presence payload, like `true`, `ON`, `home` or `not_home`.
+pub fn parse_presence(payload: &str) -> Option<bool> {
+    match payload.trim().to_lowercase().as_str() {
+        "true" | "on" | "1" | "home" | "present" | "detected" | "occupied" => Some(true),
+        "false" | "off" | "0" | "not_home" | "away" | "absent" | "clear" | "unoccupied" => {
+            Some(false)
+        }
+        _ => None,
+    }
+}
+
+/// Whether anyone is home, from any number of sources.
+#[derive(Debug)]
+pub struct Presence {
+    pub config: PresenceConfig,
+    /// last state of each source
+    sources: BTreeMap<String, bool>,
+    /// whether any source is present, and since when
+    raw: Option<(bool, DateTime<Utc>)>,
+    away: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceState<'a> {
+    pub away: bool,
+    pub sources: &'a BTreeMap<String, bool>,
+}
+
+impl Presence {
+    /// Until a source says otherwise, someone is home.
+    pub fn new(config: PresenceConfig) -> Self {
+        Self {
+            config,
+            sources: BTreeMap::new(),
+            raw: None,
+            away: false,
+        }
+    }
+
+    pub fn away(&self) -> bool {
+        self.away
+    }
+
+    pub fn state(&self) -> PresenceState<'_> {
+        PresenceState {
+            away: self.away,
+            sources: &self.sources,
+        }
+    }
+
+    /// Record the state of a source. Return true when the house becomes
+    /// empty or occupied.
+    pub fn update(&mut self, source: &str, present: bool, now: DateTime<Utc>) -> bool {
+        self.sources.insert(source.to_string(), present);
+        let any = self.sources.values().any(|p| *p);
+        if self.raw.is_none_or(|(raw, _)| raw != any) {
+            self.raw = Some((any, now));
+        }
+        self.poll(now)
+    }
+
+    /// Apply the delays. Return true when the house becomes empty or occupied.
+    pub fn poll(&mut self, now: DateTime<Utc>) -> bool {
+        let Some((any, since)) = self.raw else {
+            return false;
+        };
+        let delay = match any {
+            true => self.config.arrival_delay,
+            false => self.config.away_delay,
+        };
+        if any == self.away && now - since >= TimeDelta::from_std(delay).unwrap() {
+            self.away = !any;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presence() {
+        let mut presence = Presence::new(PresenceConfig {
+            keys: vec!["presence/**".to_string()],
+            eco: default_eco(),
+            away_delay: default_away_delay(),
+            arrival_delay: Duration::from_secs(60),
+        });
+        let t0 = Utc::now();
+        let at = |m| t0 + TimeDelta::minutes(m);
+        assert_eq!(parse_presence("not_home"), Some(false));
+        assert_eq!(parse_presence("ON"), Some(true));
+        assert_eq!(parse_presence("maybe"), None);
+
+        // one phone leaves, the other stays
+        assert!(!presence.update("phone/a", true, at(0)));
+        assert!(!presence.update("phone/b", true, at(0)));
+        assert!(!presence.update("phone/a", false, at(10)));
+        assert!(!presence.poll(at(60)));
+        assert!(!presence.away());
+
+        // the last one leaves, a short PIR trigger doesn't count as an arrival
+        assert!(!presence.update("phone/b", false, at(60)));
+        assert!(!presence.poll(at(89)));
+        assert!(presence.poll(at(90)));
+        assert!(presence.away());
+        assert!(!presence.update("pir", true, at(100)));
+        assert!(!presence.update("pir", false, at(100)));
+        assert!(!presence.poll(at(105)));
+        assert!(presence.away());
+
+        // arrival
+        assert!(!presence.update("phone/a", true, at(120)));
+        assert!(presence.poll(at(121)));
+        assert!(!presence.away());
+        assert_eq!(presence.state().sources.len(), 3);
+    }
+}
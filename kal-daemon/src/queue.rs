@@ -0,0 +1,206 @@
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::Time;
+
+/// A daemon command to run once, at some time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    pub command: String,
+    pub payload: String,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum QueueError {
+    #[error("invalid time {0:?}")]
+    InvalidTime(String),
+    #[error("{0} is in the past")]
+    Past(DateTime<Utc>),
+    #[error("can't queue {0:?}")]
+    Command(String),
+    #[error("unknown job {0}")]
+    Unknown(u64),
+}
+
+/// Commands to run later, kept in `<state_dir>/queue.json` across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Queue {
+    next: u64,
+    jobs: Vec<Job>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Queue {
+    pub fn new(state_dir: Option<PathBuf>) -> Self {
+        let path = state_dir.map(|d| d.join("queue.json"));
+        let mut queue = match &path {
+            Some(path) if path.exists() => std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| serde_json::from_str(&c).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| {
+                    warn!("can't load queue {}: {e}", path.display());
+                    Self::default()
+                }),
+            _ => Self::default(),
+        };
+        queue.path = path;
+        queue
+    }
+
+    /// Pending jobs, the first due first.
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Run `command` with `payload` at `at`, which must be after `now`.
+    pub fn add(
+        &mut self,
+        at: DateTime<Utc>,
+        command: &str,
+        payload: &str,
+        now: DateTime<Utc>,
+    ) -> Result<u64, QueueError> {
+        if at <= now {
+            return Err(QueueError::Past(at));
+        }
+        // jobs can't queue or cancel jobs
+        if command.is_empty() || command == "at" || command == "cancel" {
+            return Err(QueueError::Command(command.to_string()));
+        }
+        self.next += 1;
+        let job = Job {
+            id: self.next,
+            at,
+            command: command.to_string(),
+            payload: payload.to_string(),
+        };
+        let i = self.jobs.partition_point(|j| j.at <= at);
+        self.jobs.insert(i, job);
+        self.save();
+        Ok(self.next)
+    }
+
+    pub fn cancel(&mut self, id: u64) -> Result<Job, QueueError> {
+        let i = self
+            .jobs
+            .iter()
+            .position(|j| j.id == id)
+            .ok_or(QueueError::Unknown(id))?;
+        let job = self.jobs.remove(i);
+        self.save();
+        Ok(job)
+    }
+
+    /// Take the jobs due at `now`, including those missed while the daemon
+    /// was down, in order.
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<Job> {
+        let n = self.jobs.partition_point(|j| j.at <= now);
+        if n == 0 {
+            return Vec::new();
+        }
+        let due = self.jobs.drain(..n).collect();
+        self.save();
+        due
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            let json = serde_json::to_string_pretty(self).unwrap();
+            if let Err(e) = std::fs::write(path, json) {
+                warn!("can't save queue to {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Parse when to run a job: an RFC 3339 time, or a wall clock time in `tz`
+/// as `YYYY-MM-DD HH:MM`, `<weekday> HH:MM` or `HH:MM`, the next one after
+/// `now` for the last two.
+pub fn parse_when(s: &str, now: DateTime<Utc>, tz: Tz) -> Result<DateTime<Utc>, QueueError> {
+    let invalid = || QueueError::InvalidTime(s.to_string());
+    if let Ok(t) = s.parse::<DateTime<Utc>>() {
+        return Ok(t);
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(&s.replace('T', " "), "%Y-%m-%d %H:%M") {
+        return Ok(Time::from(t).on(t.date(), tz));
+    }
+    let (day, time) = match s.split_once(' ') {
+        Some((day, time)) => (Some(day.parse::<Weekday>().map_err(|_| invalid())?), time),
+        None => (None, s),
+    };
+    let time = time.parse::<Time>().map_err(|_| invalid())?;
+    let (today, _) = Time::at(now, tz);
+    (0..=7)
+        .map(|d| today + TimeDelta::days(d))
+        .filter(|date: &NaiveDate| day.is_none_or(|day| date.weekday() == day))
+        .map(|date| time.on(date, tz))
+        .find(|t| *t > now)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue() {
+        let paris: Tz = "Europe/Paris".parse().unwrap();
+        let utc = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        // a wednesday
+        let now = utc("2025-01-08T12:00:00Z");
+        let when = |s| parse_when(s, now, paris);
+        assert_eq!(when("fri 18:00"), Ok(utc("2025-01-10T17:00:00Z")));
+        assert_eq!(when("Wednesday 12:00"), Ok(utc("2025-01-15T11:00:00Z")));
+        assert_eq!(when("14:00"), Ok(utc("2025-01-08T13:00:00Z")));
+        assert_eq!(when("06:00"), Ok(utc("2025-01-09T05:00:00Z")));
+        assert_eq!(when("2026-11-02 06:00"), Ok(utc("2026-11-02T05:00:00Z")));
+        assert_eq!(when("2026-11-02T06:00"), Ok(utc("2026-11-02T05:00:00Z")));
+        assert_eq!(
+            when("2026-11-02T05:00:00Z"),
+            Ok(utc("2026-11-02T05:00:00Z"))
+        );
+        assert!(when("someday 18:00").is_err());
+
+        let dir = std::env::temp_dir().join(format!("kal-queue-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut queue = Queue::new(Some(dir.clone()));
+        let friday = when("fri 18:00").unwrap();
+        let monday = when("mon 06:00").unwrap();
+        assert_eq!(queue.add(monday, "mode", "Auto", now), Ok(1));
+        assert_eq!(queue.add(friday, "mode", "Off", now), Ok(2));
+        assert_eq!(queue.add(friday, "override", "eco|60", now), Ok(3));
+        assert_eq!(
+            queue.add(utc("2025-01-01T00:00:00Z"), "mode", "Off", now),
+            Err(QueueError::Past(utc("2025-01-01T00:00:00Z")))
+        );
+        assert_eq!(
+            queue.add(friday, "at", "fri 18:00|mode|Off", now),
+            Err(QueueError::Command("at".to_string()))
+        );
+        let ids = |q: &Queue| q.jobs().iter().map(|j| j.id).collect::<Vec<_>>();
+        assert_eq!(ids(&queue), [2, 3, 1]);
+        assert_eq!(queue.cancel(3).unwrap().payload, "eco|60");
+        assert_eq!(queue.cancel(3), Err(QueueError::Unknown(3)));
+
+        // jobs survive restarts, and run late if they were missed
+        let mut queue = Queue::new(Some(dir.clone()));
+        assert_eq!(ids(&queue), [2, 1]);
+        assert!(queue.due(now).is_empty());
+        let due = queue.due(monday + TimeDelta::hours(1));
+        assert_eq!(due.iter().map(|j| j.id).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(Queue::new(Some(dir.clone())).jobs(), []);
+        // ids aren't reused
+        assert_eq!(
+            queue.add(friday + TimeDelta::days(7), "mode", "Off", now),
+            Ok(4)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+const HISTORY: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liveness {
+    Unknown,
+    Online,
+    Offline,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Device {
+    pub liveness: Liveness,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub boots: u32,
+    /// latest online/offline transitions, oldest first
+    pub transitions: VecDeque<(DateTime<Utc>, Liveness)>,
+}
+
+impl Device {
+    fn new() -> Self {
+        Self {
+            liveness: Liveness::Unknown,
+            last_seen: None,
+            boots: 0,
+            transitions: VecDeque::new(),
+        }
+    }
+    fn transition(&mut self, now: DateTime<Utc>, liveness: Liveness) {
+        self.liveness = liveness;
+        if self.transitions.len() == HISTORY {
+            self.transitions.pop_front();
+        }
+        self.transitions.push_back((now, liveness));
+    }
+}
+
+/// Track devices from their `kal/tele/<device>/*` messages.
+#[derive(Debug)]
+pub struct Registry {
+    devices: BTreeMap<String, Device>,
+    timeout: Duration,
+    started: DateTime<Utc>,
+}
+
+impl Registry {
+    /// `expected` devices are tracked even if they never talk.
+    pub fn new<'a>(
+        expected: impl IntoIterator<Item = &'a str>,
+        timeout: Duration,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            devices: expected
+                .into_iter()
+                .map(|d| (d.to_string(), Device::new()))
+                .collect(),
+            timeout,
+            started: now,
+        }
+    }
+
+    pub fn get(&self, device: &str) -> Option<&Device> {
+        self.devices.get(device)
+    }
+
+    /// Record a message from `device`, return true if it just came (back) online.
+    pub fn seen(&mut self, device: &str, item: &str, now: DateTime<Utc>) -> bool {
+        let d = self
+            .devices
+            .entry(device.to_string())
+            .or_insert_with(Device::new);
+        d.last_seen = Some(now);
+        if item == "hello" {
+            d.boots += 1;
+            info!("{device} booted ({} boots)", d.boots);
+        }
+        if d.liveness == Liveness::Online {
+            return false;
+        }
+        info!("{device} online");
+        d.transition(now, Liveness::Online);
+        true
+    }
+
+    /// Mark silent devices offline, return those which just went offline.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let mut expired = Vec::new();
+        for (name, d) in self.devices.iter_mut() {
+            if d.liveness == Liveness::Offline {
+                continue;
+            }
+            let last = d.last_seen.unwrap_or(self.started);
+            if (now - last).to_std().is_ok_and(|e| e > self.timeout) {
+                warn!("{name} offline, last seen {last}");
+                d.transition(now, Liveness::Offline);
+                expired.push(name.clone());
+            }
+        }
+        expired
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.devices).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_registry() {
+        let t0 = Utc::now();
+        let minutes = |m| t0 + TimeDelta::minutes(m);
+        let mut r = Registry::new(["sensor"], Duration::from_secs(10 * 60), t0);
+        assert_eq!(r.get("sensor").unwrap().liveness, Liveness::Unknown);
+
+        assert!(r.seen("relay", "hello", minutes(1)));
+        assert!(!r.seen("relay", "relay", minutes(6)));
+        assert_eq!(r.get("relay").unwrap().boots, 1);
+
+        assert_eq!(r.expire(minutes(11)), vec!["sensor".to_string()]);
+        assert!(r.expire(minutes(12)).is_empty());
+        assert_eq!(r.expire(minutes(17)), vec!["relay".to_string()]);
+
+        assert!(r.seen("relay", "hello", minutes(20)));
+        let relay = r.get("relay").unwrap();
+        assert_eq!(relay.boots, 2);
+        assert_eq!(relay.liveness, Liveness::Online);
+        assert_eq!(relay.transitions.len(), 3);
+    }
+}
@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::Temperature;
+
+/// First order thermal model of a heated room.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Room {
+    pub temperature: f64,
+    pub outdoor: f64,
+    /// heat loss through walls and air changes, in W/K
+    pub heat_loss: f64,
+    /// heater power when the relay is on, in W
+    pub heater_power: f64,
+    /// thermal mass of the room, in J/K
+    pub capacity: f64,
+    pub heating: bool,
+}
+
+impl Default for Room {
+    fn default() -> Self {
+        Self {
+            temperature: 15.0,
+            outdoor: 5.0,
+            heat_loss: 150.0,
+            heater_power: 2000.0,
+            capacity: 5e6,
+            heating: false,
+        }
+    }
+}
+
+impl Room {
+    pub fn temperature(&self) -> Temperature {
+        self.temperature.into()
+    }
+
+    /// Temperature the room would settle at with the heater kept on or off.
+    pub fn equilibrium(&self, heating: bool) -> f64 {
+        let power = if heating { self.heater_power } else { 0.0 };
+        self.outdoor + power / self.heat_loss
+    }
+
+    /// Advance the model by `dt`, exactly for a constant heater state.
+    pub fn step(&mut self, dt: Duration) {
+        let target = self.equilibrium(self.heating);
+        let decay = (-dt.as_secs_f64() * self.heat_loss / self.capacity).exp();
+        self.temperature = target + (self.temperature - target) * decay;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room() {
+        let hour = Duration::from_secs(3600);
+        let mut room = Room::default();
+        room.step(hour);
+        assert!(room.temperature < 15.0);
+
+        room.heating = true;
+        let before = room.temperature;
+        room.step(hour);
+        assert!(room.temperature > before);
+
+        for _ in 0..24 * 7 {
+            room.step(hour);
+        }
+        assert!((room.temperature - room.equilibrium(true)).abs() < 0.01);
+
+        // one big step and many small steps agree
+        let mut a = Room::default();
+        let mut b = a.clone();
+        a.step(hour * 6);
+        for _ in 0..6 * 60 {
+            b.step(Duration::from_secs(60));
+        }
+        assert!((a.temperature - b.temperature).abs() < 1e-9);
+    }
+}
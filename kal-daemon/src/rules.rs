@@ -0,0 +1,339 @@
+use chrono::{DateTime, Datelike, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use zenoh::key_expr::KeyExpr;
+
+use crate::Time;
+use crate::time::hhmm;
+
+/// What makes a rule fire.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Trigger {
+    /// a number on `key` staying above and/or below some values for a while
+    Threshold {
+        key: String,
+        above: Option<f64>,
+        below: Option<f64>,
+        #[serde(default, rename = "for", with = "humantime_serde")]
+        duration: Duration,
+    },
+    /// a new payload on `key`, or only this one
+    Change { key: String, to: Option<String> },
+    /// a time of the day, on some days, every day if empty
+    Time {
+        #[serde(deserialize_with = "hhmm")]
+        at: Time,
+        #[serde(default)]
+        days: Vec<Weekday>,
+    },
+}
+
+/// What must hold when a rule fires.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Condition {
+    /// the last payload on `key`, as is or as a number
+    State {
+        key: String,
+        equals: Option<String>,
+        above: Option<f64>,
+        below: Option<f64>,
+    },
+    /// between two times of the day, which may wrap around midnight
+    Time {
+        #[serde(deserialize_with = "hhmm")]
+        after: Time,
+        #[serde(deserialize_with = "hhmm")]
+        before: Time,
+    },
+}
+
+/// Put `payload` on `key`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Put {
+    pub key: String,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Put>,
+}
+
+impl Rule {
+    /// Keys of its trigger and conditions.
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        let trigger = match &self.trigger {
+            Trigger::Threshold { key, .. } | Trigger::Change { key, .. } => Some(key.as_str()),
+            Trigger::Time { .. } => None,
+        };
+        let conditions = self.conditions.iter().filter_map(|c| match c {
+            Condition::State { key, .. } => Some(key.as_str()),
+            Condition::Time { .. } => None,
+        });
+        trigger.into_iter().chain(conditions)
+    }
+
+    /// All keys must be valid, and as payloads are matched by key, those of
+    /// the trigger and conditions must not be wildcards.
+    pub fn check(&self) -> Result<(), String> {
+        let actions = self.actions.iter().map(|a| a.key.as_str());
+        for key in self.keys().chain(actions) {
+            if let Err(e) = KeyExpr::try_from(key) {
+                return Err(format!("rule {:?}: {e}", self.name));
+            }
+        }
+        match self.keys().find(|k| k.contains('*')) {
+            Some(key) => Err(format!("rule {:?}: wildcard key {key}", self.name)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn within(v: f64, above: Option<f64>, below: Option<f64>) -> bool {
+    above.is_none_or(|a| v > a) && below.is_none_or(|b| v < b)
+}
+
+/// Threshold progress of a rule.
+#[derive(Debug, Default, Clone)]
+struct Held {
+    since: Option<DateTime<Utc>>,
+    fired: bool,
+}
+
+/// Rules from the configuration, with the last payload of the keys they
+/// watch.
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    held: Vec<Held>,
+    values: BTreeMap<String, String>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let held = vec![Held::default(); rules.len()];
+        Self {
+            rules,
+            held,
+            ..Default::default()
+        }
+    }
+
+    /// Keys of triggers and conditions, to subscribe to.
+    pub fn keys(&self) -> BTreeSet<&str> {
+        self.rules.iter().flat_map(|r| r.keys()).collect()
+    }
+
+    fn holds(&self, condition: &Condition, now: DateTime<Utc>, tz: Tz) -> bool {
+        match condition {
+            Condition::State {
+                key,
+                equals,
+                above,
+                below,
+            } => {
+                let Some(value) = self.values.get(key) else {
+                    return false;
+                };
+                let number = || value.trim().parse::<f64>().ok();
+                equals.as_ref().is_none_or(|e| e == value)
+                    && (above.is_none() && below.is_none()
+                        || number().is_some_and(|v| within(v, *above, *below)))
+            }
+            Condition::Time { after, before } => {
+                let (_, t) = Time::at(now, tz);
+                if after <= before {
+                    *after <= t && t < *before
+                } else {
+                    *after <= t || t < *before
+                }
+            }
+        }
+    }
+
+    /// The rule `i` fires, if its conditions hold.
+    fn fire(&self, i: usize, now: DateTime<Utc>, tz: Tz) -> Option<&Rule> {
+        let rule = &self.rules[i];
+        rule.conditions
+            .iter()
+            .all(|c| self.holds(c, now, tz))
+            .then_some(rule)
+    }
+
+    /// A threshold rule which held for long enough fires once its conditions
+    /// hold, and not again until it stops holding.
+    fn check_held(&mut self, i: usize, now: DateTime<Utc>, tz: Tz) -> Option<&Rule> {
+        let Trigger::Threshold { duration, .. } = self.rules[i].trigger else {
+            return None;
+        };
+        let held = &self.held[i];
+        let since = held.since.filter(|_| !held.fired)?;
+        if now - since < TimeDelta::from_std(duration).unwrap() {
+            return None;
+        }
+        self.fire(i, now, tz)?;
+        self.held[i].fired = true;
+        Some(&self.rules[i])
+    }
+
+    /// A payload on `key`: the rules it makes fire.
+    pub fn sample(&mut self, key: &str, payload: &str, now: DateTime<Utc>, tz: Tz) -> Vec<Rule> {
+        let previous = self.values.insert(key.to_string(), payload.to_string());
+        let mut fired = Vec::new();
+        for i in 0..self.rules.len() {
+            match &self.rules[i].trigger {
+                Trigger::Threshold {
+                    key: k,
+                    above,
+                    below,
+                    ..
+                } if k == key => {
+                    let held = &mut self.held[i];
+                    match payload.trim().parse() {
+                        Ok(v) if within(v, *above, *below) => {
+                            held.since.get_or_insert(now);
+                        }
+                        _ => *held = Held::default(),
+                    }
+                    fired.extend(self.check_held(i, now, tz).cloned());
+                }
+                Trigger::Change { key: k, to } if k == key => {
+                    let changed = previous.as_ref().is_some_and(|p| p != payload);
+                    if changed && to.as_ref().is_none_or(|to| to == payload) {
+                        fired.extend(self.fire(i, now, tz).cloned());
+                    }
+                }
+                _ => (),
+            }
+        }
+        fired
+    }
+
+    /// Time passing: the time rules due since the last tick, and the
+    /// threshold ones which have now held for long enough.
+    pub fn tick(&mut self, now: DateTime<Utc>, tz: Tz) -> Vec<Rule> {
+        let last = self.last.replace(now);
+        let mut fired = Vec::new();
+        for i in 0..self.rules.len() {
+            match &self.rules[i].trigger {
+                Trigger::Threshold { .. } => fired.extend(self.check_held(i, now, tz).cloned()),
+                Trigger::Time { at, days } => {
+                    let Some(last) = last else { continue };
+                    let (first, _) = Time::at(last, tz);
+                    let (today, _) = Time::at(now, tz);
+                    let due = first.iter_days().take_while(|d| *d <= today).any(|d| {
+                        let t = at.on(d, tz);
+                        (days.is_empty() || days.contains(&d.weekday())) && last < t && t <= now
+                    });
+                    if due {
+                        fired.extend(self.fire(i, now, tz).cloned());
+                    }
+                }
+                Trigger::Change { .. } => (),
+            }
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let config: BTreeMap<String, Vec<Rule>> = toml::from_str(
+            r#"
+            [[rules]]
+            name = "fan"
+            trigger = { type = "threshold", key = "kal/tele/bathroom/humidity", above = 70, for = "10min" }
+            conditions = [{ type = "time", after = "07:00", before = "23:00" }]
+            actions = [{ key = "kal/cmnd/fan/relay", payload = "On" }]
+
+            [[rules]]
+            name = "led"
+            trigger = { type = "time", at = "22:00", days = ["Mon"] }
+            actions = [{ key = "kal/cmnd/led/relay", payload = "Off" }]
+
+            [[rules]]
+            name = "door"
+            trigger = { type = "change", key = "kal/tele/door/state", to = "Open" }
+            conditions = [{ type = "state", key = "kal/tele/daemon/mode", equals = "Auto" }]
+            actions = [{ key = "kal/cmnd/daemon/mode", payload = "Off" }]
+            "#,
+        )
+        .unwrap();
+        let mut rules = Rules::new(config["rules"].clone());
+        assert_eq!(
+            rules.keys().into_iter().collect::<Vec<_>>(),
+            [
+                "kal/tele/bathroom/humidity",
+                "kal/tele/daemon/mode",
+                "kal/tele/door/state"
+            ]
+        );
+
+        let paris: Tz = "Europe/Paris".parse().unwrap();
+        // monday 6th, 12:00 in Paris
+        let t0 = "2025-01-06T11:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let at = |m| t0 + TimeDelta::minutes(m);
+        let names = |fired: Vec<Rule>| fired.into_iter().map(|r| r.name).collect::<Vec<_>>();
+        let humidity = "kal/tele/bathroom/humidity";
+
+        // humid for 10 minutes, once
+        assert!(rules.sample(humidity, "75", at(0), paris).is_empty());
+        assert!(rules.sample(humidity, "72", at(5), paris).is_empty());
+        assert!(rules.tick(at(9), paris).is_empty());
+        assert_eq!(names(rules.tick(at(10), paris)), ["fan"]);
+        assert!(rules.sample(humidity, "80", at(15), paris).is_empty());
+        // dry again, then humid for too short
+        assert!(rules.sample(humidity, "60", at(20), paris).is_empty());
+        assert!(rules.sample(humidity, "75", at(25), paris).is_empty());
+        assert!(rules.sample(humidity, "60", at(30), paris).is_empty());
+        assert!(rules.tick(at(40), paris).is_empty());
+
+        // at 22:00 on mondays
+        assert!(rules.tick(at(599), paris).is_empty());
+        assert_eq!(names(rules.tick(at(601), paris)), ["led"]);
+        assert!(rules.tick(at(602), paris).is_empty());
+        assert!(rules.tick(at(24 * 60 + 601), paris).is_empty());
+
+        // the door opens, only in Auto
+        let door = "kal/tele/door/state";
+        assert!(rules.sample(door, "Closed", at(0), paris).is_empty());
+        assert!(rules.sample(door, "Open", at(1), paris).is_empty());
+        rules.sample("kal/tele/daemon/mode", "Auto", at(2), paris);
+        assert!(rules.sample(door, "Closed", at(3), paris).is_empty());
+        assert_eq!(names(rules.sample(door, "Open", at(4), paris)), ["door"]);
+
+        // and the fan only during the day: humid from 1:00, it starts at 7:00
+        rules.sample(humidity, "75", at(13 * 60), paris);
+        assert!(rules.tick(at(13 * 60 + 10), paris).is_empty());
+        assert!(rules.tick(at(18 * 60 + 59), paris).is_empty());
+        assert_eq!(names(rules.tick(at(19 * 60), paris)), ["fan"]);
+        assert!(rules.tick(at(19 * 60 + 1), paris).is_empty());
+
+        // payloads are matched by key
+        let mut wild = config["rules"][0].clone();
+        assert!(wild.check().is_ok());
+        wild.trigger = Trigger::Change {
+            key: "kal/tele/*/state".to_string(),
+            to: None,
+        };
+        assert!(wild.check().is_err());
+        let mut invalid = config["rules"][0].clone();
+        invalid.actions[0].key = "kal/cmnd//relay".to_string();
+        assert!(invalid.check().is_err());
+    }
+}
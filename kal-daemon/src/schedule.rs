@@ -1,75 +1,793 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
 use itertools::Itertools;
 use log::debug;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use thiserror::Error;
 
-use crate::{Temperature, Time};
+use crate::{Clock, Location, Temperature, Time};
 
-#[derive(Debug)]
+/// Setpoint replacing the schedule until some time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Override {
+    pub temperature: Temperature,
+    pub until: DateTime<Utc>,
+    /// preset the temperature comes from, if any
+    pub preset: Option<String>,
+}
+
+/// A literal temperature, or the name of a preset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Setpoint {
+    Temperature(Temperature),
+    Preset(String),
+}
+
+impl From<Temperature> for Setpoint {
+    fn from(value: Temperature) -> Self {
+        Self::Temperature(value)
+    }
+}
+
+/// Parse a finite number, or a preset name
+impl std::str::FromStr for Setpoint {
+    type Err = ScheduleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<f64>() {
+            Ok(t) if t.is_finite() => Ok(Self::Temperature(t.into())),
+            Ok(_) => Err(ScheduleError::Invalid(s.to_string())),
+            Err(_) if !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                Ok(Self::Preset(s.to_string()))
+            }
+            Err(_) => Err(ScheduleError::Invalid(s.to_string())),
+        }
+    }
+}
+
+/// When a schedule point happens: a time of the day, or minutes from sunrise
+/// or sunset, resolved for each date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Anchor {
+    Time(Time),
+    Sunrise(i32),
+    Sunset(i32),
+}
+
+impl From<Time> for Anchor {
+    fn from(value: Time) -> Self {
+        Self::Time(value)
+    }
+}
+
+/// Minutes since midnight, `HH:MM`, or `sunrise`/`sunset` with an offset in
+/// minutes of at most a day, eg. `sunset-30`
+impl std::str::FromStr for Anchor {
+    type Err = ScheduleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ScheduleError::InvalidTime(s.to_string());
+        let solar = |rest: &str| match rest {
+            "" => Ok(0),
+            _ if rest.starts_with(['+', '-']) => rest
+                .parse::<i32>()
+                .ok()
+                .filter(|o| o.unsigned_abs() <= Time::MAX.minutes())
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        };
+        if let Some(rest) = s.strip_prefix("sunrise") {
+            Ok(Self::Sunrise(solar(rest)?))
+        } else if let Some(rest) = s.strip_prefix("sunset") {
+            Ok(Self::Sunset(solar(rest)?))
+        } else if let Ok(minutes) = s.parse() {
+            Time::from_minutes(minutes)
+                .map(Self::Time)
+                .map_err(|_| invalid())
+        } else {
+            // points are kept in minutes
+            match s.parse::<Time>() {
+                Ok(t) if t.seconds() % 60 == 0 => Ok(Self::Time(t)),
+                _ => Err(invalid()),
+            }
+        }
+    }
+}
+
+/// As parsed, with minutes for times
+impl std::fmt::Display for Anchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Time(t) => write!(f, "{}", t.minutes()),
+            Self::Sunrise(0) => write!(f, "sunrise"),
+            Self::Sunset(0) => write!(f, "sunset"),
+            Self::Sunrise(offset) => write!(f, "sunrise{offset:+}"),
+            Self::Sunset(offset) => write!(f, "sunset{offset:+}"),
+        }
+    }
+}
+
+/// Times as minutes, like before solar points, others as strings
+impl Serialize for Anchor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Time(t) => t.serialize(serializer),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Anchor {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Anchor;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    f,
+                    "minutes since midnight, or sunrise/sunset with an offset"
+                )
+            }
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Anchor, E> {
+                self.visit_str(&v.to_string())
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Anchor, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Anchor {
+    /// Time of the day on `date` in `tz`, if the sun rises and sets, kept
+    /// strictly between 0H00 and 24H00.
+    pub fn on(self, date: NaiveDate, location: Option<Location>, tz: Tz) -> Option<Time> {
+        let (event, offset) = match self {
+            Self::Time(t) => return Some(t),
+            Self::Sunrise(offset) => (location?.sun(date)?.0, offset),
+            Self::Sunset(offset) => (location?.sun(date)?.1, offset),
+        };
+        let minutes =
+            (Time::from(event.with_timezone(&tz)).minutes() as i32).saturating_add(offset);
+        Time::from_minutes(minutes.clamp(1, Time::MAX.minutes() as i32 - 1) as u32).ok()
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ScheduleError {
+    #[error("invalid setpoint {0:?}")]
+    Invalid(String),
+    #[error("invalid time {0:?}")]
+    InvalidTime(String),
+    #[error("sunrise and sunset need a location")]
+    NoLocation,
+    #[error("unknown preset {0:?}")]
+    UnknownPreset(String),
+    #[error("preset {0:?} is used by schedule points")]
+    PresetInUse(String),
+    #[error("unknown profile {0:?}")]
+    UnknownProfile(String),
+    #[error("profile {0:?} already exists")]
+    ProfileExists(String),
+    #[error("profile {0:?} is active")]
+    ActiveProfile(String),
+}
+
+type Points = BTreeMap<Anchor, Setpoint>;
+
+/// Named profiles of points, one of them active, sharing presets. Points
+/// follow the wall clock of the timezone: see [`Time::at`] and [`Time::on`]
+/// for what happens when it changes.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Schedule {
-    points: BTreeMap<Time, Temperature>,
+    profiles: BTreeMap<String, Points>,
+    active: String,
+    presets: BTreeMap<String, Temperature>,
+    #[serde(skip)]
+    r#override: Option<Override>,
+    #[serde(skip)]
+    location: Option<Location>,
+    #[serde(skip)]
+    timezone: Tz,
+}
+
+/// name of the first profile
+pub const DEFAULT_PROFILE: &str = "normal";
+
+/// `comfort`, `eco` and `frost`
+pub fn default_presets() -> BTreeMap<String, Temperature> {
+    BTreeMap::from([
+        ("comfort".to_string(), 19.0.into()),
+        ("eco".to_string(), 16.0.into()),
+        ("frost".to_string(), 7.0.into()),
+    ])
 }
 
 impl Default for Schedule {
     fn default() -> Self {
-        Self {
-            points: BTreeMap::from([
-                (Time::from_hours_unchecked(0.0), 14.0.into()),
-                (Time::from_hours_unchecked(5.0), 15.5.into()),
-                (Time::from_hours_unchecked(7.0), 17.0.into()),
-                (Time::from_hours_unchecked(22.0), 17.0.into()),
-                (Time::from_hours_unchecked(24.0), 14.0.into()),
-            ]),
-        }
+        Self::with_points(BTreeMap::from([
+            (
+                Time::from_hours_unchecked(0.0).into(),
+                Temperature::from(14.0).into(),
+            ),
+            (
+                Time::from_hours_unchecked(5.0).into(),
+                Temperature::from(15.5).into(),
+            ),
+            (
+                Time::from_hours_unchecked(7.0).into(),
+                Temperature::from(17.0).into(),
+            ),
+            (
+                Time::from_hours_unchecked(22.0).into(),
+                Temperature::from(17.0).into(),
+            ),
+            (
+                Time::from_hours_unchecked(24.0).into(),
+                Temperature::from(14.0).into(),
+            ),
+        ]))
     }
 }
 
 impl Schedule {
     pub fn new(temperature: Temperature) -> Self {
+        Self::with_points(BTreeMap::from([
+            (Time::MIN.into(), temperature.into()),
+            (Time::MAX.into(), temperature.into()),
+        ]))
+    }
+    fn with_points(points: Points) -> Self {
         Self {
-            points: BTreeMap::from([
-                (Time::from_hours_unchecked(0.), temperature),
-                (Time::from_hours_unchecked(24.), temperature),
-            ]),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), points)]),
+            active: DEFAULT_PROFILE.to_string(),
+            presets: default_presets(),
+            r#override: None,
+            location: None,
+            timezone: Tz::UTC,
+        }
+    }
+
+    /// Where sunrise and sunset are computed for.
+    pub fn set_location(&mut self, location: Option<Location>) {
+        self.location = location;
+    }
+    /// Timezone of the points, UTC by default.
+    pub fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = timezone;
+    }
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    fn points_mut(&mut self, profile: Option<&str>) -> Result<&mut Points, ScheduleError> {
+        let name = profile.unwrap_or(&self.active);
+        self.profiles
+            .get_mut(name)
+            .ok_or_else(|| ScheduleError::UnknownProfile(name.to_string()))
+    }
+    /// Add or replace a point in a profile, the active one by default.
+    pub fn insert(
+        &mut self,
+        profile: Option<&str>,
+        time: impl Into<Anchor>,
+        setpoint: impl Into<Setpoint>,
+    ) -> Result<(), ScheduleError> {
+        let (time, setpoint) = (time.into(), setpoint.into());
+        self.resolve(&setpoint)?;
+        if !matches!(time, Anchor::Time(_)) && self.location.is_none() {
+            return Err(ScheduleError::NoLocation);
         }
+        self.points_mut(profile)?.insert(time, setpoint);
+        Ok(())
+    }
+    /// Points of the active profile, with the temperatures of their presets.
+    pub fn points(&self) -> impl Iterator<Item = (Anchor, Temperature, &Setpoint)> + '_ {
+        self.points_of(&self.active).unwrap()
     }
-    pub fn insert(&mut self, time: Time, temperature: Temperature) {
-        self.points.insert(time, temperature);
+    pub fn points_of(
+        &self,
+        profile: &str,
+    ) -> Result<impl Iterator<Item = (Anchor, Temperature, &Setpoint)> + '_, ScheduleError> {
+        let points = self
+            .profiles
+            .get(profile)
+            .ok_or_else(|| ScheduleError::UnknownProfile(profile.to_string()))?;
+        Ok(points
+            .iter()
+            .map(|(t, s)| (*t, self.resolve(s).unwrap(), s)))
     }
-    pub fn remove(&mut self, time: Time) {
+    pub fn remove(
+        &mut self,
+        profile: Option<&str>,
+        time: impl Into<Anchor>,
+    ) -> Result<(), ScheduleError> {
+        let time = time.into();
+        let points = self.points_mut(profile)?;
         // can't remove 0:00 and 24:00
-        if Time::MIN < time && time < Time::MAX {
-            self.points.remove(&time);
+        if time != Time::MIN.into() && time != Time::MAX.into() {
+            points.remove(&time);
         }
+        Ok(())
+    }
+    /// Points of the active profile on `date`, in order, with solar points
+    /// resolved. Those with a fixed time win over solar ones at the same time.
+    pub fn day(&self, date: NaiveDate) -> Vec<(Time, Temperature)> {
+        let mut day = BTreeMap::new();
+        for (anchor, temperature, _) in self.points().sorted_by_key(|(a, _, _)| match a {
+            Anchor::Time(_) => 1,
+            _ => 0,
+        }) {
+            if let Some(time) = anchor.on(date, self.location, self.timezone) {
+                day.insert(time, temperature);
+            }
+        }
+        day.into_iter().collect()
     }
 
-    pub fn auto(&self, t: Time, v: Temperature) -> bool {
-        debug!("current: {v} @ {t}");
-        for ((t1, v1), (t2, v2)) in self.points.iter().tuple_windows() {
-            if *t1 <= t && t <= *t2 {
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+    pub fn profiles(&self) -> impl Iterator<Item = &String> {
+        self.profiles.keys()
+    }
+    pub fn activate(&mut self, profile: &str) -> Result<(), ScheduleError> {
+        if !self.profiles.contains_key(profile) {
+            return Err(ScheduleError::UnknownProfile(profile.to_string()));
+        }
+        debug!("profile {profile}");
+        self.active = profile.to_string();
+        Ok(())
+    }
+    pub fn copy_profile(&mut self, from: &str, to: &str) -> Result<(), ScheduleError> {
+        if self.profiles.contains_key(to) {
+            return Err(ScheduleError::ProfileExists(to.to_string()));
+        }
+        let points = self
+            .profiles
+            .get(from)
+            .ok_or_else(|| ScheduleError::UnknownProfile(from.to_string()))?;
+        self.profiles.insert(to.to_string(), points.clone());
+        Ok(())
+    }
+    pub fn rename_profile(&mut self, from: &str, to: &str) -> Result<(), ScheduleError> {
+        self.copy_profile(from, to)?;
+        self.profiles.remove(from);
+        if self.active == from {
+            self.active = to.to_string();
+        }
+        Ok(())
+    }
+    pub fn delete_profile(&mut self, profile: &str) -> Result<(), ScheduleError> {
+        if self.active == profile {
+            return Err(ScheduleError::ActiveProfile(profile.to_string()));
+        }
+        self.profiles
+            .remove(profile)
+            .map(|_| ())
+            .ok_or_else(|| ScheduleError::UnknownProfile(profile.to_string()))
+    }
+
+    pub fn presets(&self) -> &BTreeMap<String, Temperature> {
+        &self.presets
+    }
+    /// Add or change a preset, which moves every point and override using it.
+    pub fn set_preset(&mut self, name: &str, temperature: Temperature) {
+        debug!("preset {name} {temperature}");
+        self.presets.insert(name.to_string(), temperature);
+        if let Some(o) = &mut self.r#override
+            && o.preset.as_deref() == Some(name)
+        {
+            o.temperature = temperature;
+        }
+    }
+    /// Remove a preset, if no point uses it.
+    pub fn remove_preset(&mut self, name: &str) -> Result<(), ScheduleError> {
+        let preset = Setpoint::Preset(name.to_string());
+        if self
+            .profiles
+            .values()
+            .flat_map(|p| p.values())
+            .any(|s| *s == preset)
+        {
+            return Err(ScheduleError::PresetInUse(name.to_string()));
+        }
+        self.presets.remove(name);
+        Ok(())
+    }
+    pub fn resolve(&self, setpoint: &Setpoint) -> Result<Temperature, ScheduleError> {
+        match setpoint {
+            Setpoint::Temperature(t) => Ok(*t),
+            Setpoint::Preset(name) => self
+                .presets
+                .get(name)
+                .copied()
+                .ok_or_else(|| ScheduleError::UnknownPreset(name.clone())),
+        }
+    }
+
+    /// Hold `setpoint` until `until`, or until the next schedule point.
+    pub fn set_override(
+        &mut self,
+        setpoint: impl Into<Setpoint>,
+        until: Option<DateTime<Utc>>,
+        clock: &dyn Clock,
+    ) -> Result<(), ScheduleError> {
+        let setpoint = setpoint.into();
+        let temperature = self.resolve(&setpoint)?;
+        let until = until.unwrap_or_else(|| {
+            let (date, t) = Time::at(clock.now(), self.timezone);
+            self.next_point(date, t).on(date, self.timezone)
+        });
+        debug!("override {temperature} until {until}");
+        let preset = match setpoint {
+            Setpoint::Preset(name) => Some(name),
+            Setpoint::Temperature(_) => None,
+        };
+        self.r#override = Some(Override {
+            temperature,
+            until,
+            preset,
+        });
+        Ok(())
+    }
+    pub fn clear_override(&mut self) {
+        self.r#override = None;
+    }
+    /// The override, if it has not expired yet.
+    pub fn current_override(&self, clock: &dyn Clock) -> Option<Override> {
+        self.override_at(clock.now())
+    }
+    pub fn override_at(&self, t: DateTime<Utc>) -> Option<Override> {
+        self.r#override.clone().filter(|o| t < o.until)
+    }
+
+    /// First schedule point on `date` strictly after `t`, or 24H00.
+    pub fn next_point(&self, date: NaiveDate, t: Time) -> Time {
+        self.day(date)
+            .into_iter()
+            .map(|(p, _)| p)
+            .find(|p| *p > t)
+            .unwrap_or(Time::MAX)
+    }
+
+    pub fn setpoint_at(&self, date: NaiveDate, t: Time) -> Temperature {
+        for ((t1, v1), (t2, v2)) in self.day(date).into_iter().tuple_windows() {
+            if t1 <= t && t <= t2 {
                 debug!("segment: {v1} @ {t1} -> {v2} @ {t2}");
-                let ratio = (t - *t1) / (*t2 - *t1);
-                return v < *v1 + (*v2 - *v1) * ratio;
+                let ratio = (t - t1) / (t2 - t1);
+                return v1 + (v2 - v1) * ratio;
             }
         }
         unreachable!()
     }
+    /// Setpoint at any time, taking the override into account.
+    pub fn setpoint_on(&self, t: DateTime<Utc>) -> Temperature {
+        match self.override_at(t) {
+            Some(o) => o.temperature,
+            None => {
+                let (date, t) = Time::at(t, self.timezone);
+                self.setpoint_at(date, t)
+            }
+        }
+    }
+    /// End of the override, or next schedule point after `t`, with its setpoint.
+    pub fn next_change(&self, t: DateTime<Utc>) -> (DateTime<Utc>, Temperature) {
+        if let Some(o) = self.override_at(t) {
+            return (o.until, self.setpoint_on(o.until));
+        }
+        let (date, t) = Time::at(t, self.timezone);
+        let next = self.next_point(date, t).on(date, self.timezone);
+        (next, self.setpoint_on(next))
+    }
+    /// Setpoints from `from` to `to`, every `step`.
+    pub fn curve(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        step: TimeDelta,
+    ) -> Vec<(DateTime<Utc>, Temperature)> {
+        std::iter::successors(Some(from), |t| t.checked_add_signed(step))
+            .take_while(|t| *t <= to)
+            .map(|t| (t, self.setpoint_on(t)))
+            .collect()
+    }
+    pub fn setpoint(&self, clock: &dyn Clock) -> Temperature {
+        self.setpoint_on(clock.now())
+    }
+
+    pub fn auto(&self, date: NaiveDate, t: Time, v: Temperature) -> bool {
+        debug!("current: {v} @ {t}");
+        v < self.setpoint_at(date, t)
+    }
+    /// Should we heat now, taking overrides into account.
+    pub fn heat(&self, clock: &dyn Clock, v: Temperature) -> bool {
+        debug!("current: {v}");
+        v < self.setpoint(clock)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ManualClock;
+    use chrono::{NaiveDate, TimeDelta};
 
     #[test]
     fn test_schedule() {
+        let day = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
         env_logger::init();
         let s = Schedule::default();
-        assert!(s.auto(Time::from_hours_unchecked(0.0), 13.0.into()));
-        assert!(!s.auto(Time::from_hours_unchecked(0.0), 15.0.into()));
-        assert!(s.auto(Time::from_hours_unchecked(5.0), 15.0.into()));
-        assert!(!s.auto(Time::from_hours_unchecked(5.0), 16.0.into()));
-        assert!(!s.auto(Time::from_hours_unchecked(5.1), 16.0.into()));
-        assert!(s.auto(Time::from_hours_unchecked(7.9), 16.0.into()));
-        assert!(s.auto(Time::from_hours_unchecked(24.0), 13.0.into()));
-        assert!(!s.auto(Time::from_hours_unchecked(24.0), 15.0.into()));
+        assert!(s.auto(day, Time::from_hours_unchecked(0.0), 13.0.into()));
+        assert!(!s.auto(day, Time::from_hours_unchecked(0.0), 15.0.into()));
+        assert!(s.auto(day, Time::from_hours_unchecked(5.0), 15.0.into()));
+        assert!(!s.auto(day, Time::from_hours_unchecked(5.0), 16.0.into()));
+        assert!(!s.auto(day, Time::from_hours_unchecked(5.1), 16.0.into()));
+        assert!(s.auto(day, Time::from_hours_unchecked(7.9), 16.0.into()));
+        assert!(s.auto(day, Time::from_hours_unchecked(24.0), 13.0.into()));
+        assert!(!s.auto(day, Time::from_hours_unchecked(24.0), 15.0.into()));
+    }
+
+    #[test]
+    fn test_override() {
+        let at = |h, m| {
+            NaiveDate::from_ymd_opt(2025, 1, 6)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let clock = ManualClock::new(at(8, 0));
+        let mut s = Schedule::default();
+        assert_eq!(s.setpoint(&clock), 17.0.into());
+        assert!(s.heat(&clock, 16.0.into()));
+
+        // until the next schedule point
+        s.set_override(Temperature::from(15.0), None, &clock)
+            .unwrap();
+        assert!(!s.heat(&clock, 16.0.into()));
+        clock.set(at(21, 59));
+        assert_eq!(s.setpoint(&clock), 15.0.into());
+        clock.advance(TimeDelta::minutes(1));
+        assert_eq!(s.setpoint(&clock), 17.0.into());
+
+        // for some time
+        s.set_override(Temperature::from(20.0), Some(at(22, 30)), &clock)
+            .unwrap();
+        clock.advance(TimeDelta::minutes(29));
+        assert_eq!(s.setpoint(&clock), 20.0.into());
+        clock.advance(TimeDelta::minutes(1));
+        assert!(s.current_override(&clock).is_none());
+
+        s.set_override(Temperature::from(20.0), None, &clock)
+            .unwrap();
+        assert_eq!(
+            s.current_override(&clock).unwrap().until,
+            at(0, 0) + TimeDelta::days(1)
+        );
+        s.clear_override();
+        assert!(s.current_override(&clock).is_none());
+    }
+
+    #[test]
+    fn test_presets() {
+        let day = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
+        let clock = ManualClock::new(Utc::now());
+        let mut s = Schedule::new(14.0.into());
+        let t = Time::from_hours_unchecked(12.0);
+        let comfort: Setpoint = "comfort".parse().unwrap();
+        assert_eq!("17.5".parse(), Ok(Setpoint::Temperature(17.5.into())));
+        assert!("eco|3".parse::<Setpoint>().is_err());
+        for s in ["nan", "NaN", "inf", "-inf", "infinity"] {
+            assert!(s.parse::<Setpoint>().is_err());
+        }
+        s.insert(None, Time::from_hours_unchecked(8.0), comfort.clone())
+            .unwrap();
+        s.insert(None, Time::from_hours_unchecked(18.0), comfort.clone())
+            .unwrap();
+        assert_eq!(s.setpoint_at(day, t), 19.0.into());
+        assert_eq!(
+            s.insert(None, t, Setpoint::Preset("sauna".to_string())),
+            Err(ScheduleError::UnknownPreset("sauna".to_string()))
+        );
+
+        // changing the preset moves the points and the override using it
+        s.set_override(comfort, None, &clock).unwrap();
+        s.set_preset("comfort", 18.5.into());
+        assert_eq!(s.setpoint_at(day, t), 18.5.into());
+        let o = s.current_override(&clock).unwrap();
+        assert_eq!(o.temperature, 18.5.into());
+        assert_eq!(o.preset.as_deref(), Some("comfort"));
+        assert_eq!(
+            s.remove_preset("comfort"),
+            Err(ScheduleError::PresetInUse("comfort".to_string()))
+        );
+        assert!(s.remove_preset("frost").is_ok());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let day = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
+        let mut s = Schedule::new(14.0.into());
+        let t = Time::from_hours_unchecked(12.0);
+        s.copy_profile("normal", "guests").unwrap();
+        s.insert(Some("guests"), t, Temperature::from(20.0))
+            .unwrap();
+        assert_eq!(s.setpoint_at(day, t), 14.0.into());
+        s.activate("guests").unwrap();
+        assert_eq!(s.setpoint_at(day, t), 20.0.into());
+        assert_eq!(
+            s.copy_profile("normal", "guests"),
+            Err(ScheduleError::ProfileExists("guests".to_string()))
+        );
+        assert_eq!(
+            s.delete_profile("guests"),
+            Err(ScheduleError::ActiveProfile("guests".to_string()))
+        );
+
+        s.rename_profile("guests", "holidays").unwrap();
+        assert_eq!(s.active(), "holidays");
+        s.delete_profile("normal").unwrap();
+        assert_eq!(s.profiles().collect::<Vec<_>>(), ["holidays"]);
+        assert_eq!(
+            s.activate("normal"),
+            Err(ScheduleError::UnknownProfile("normal".to_string()))
+        );
+
+        // everything but the override is saved
+        let json = serde_json::to_string(&s).unwrap();
+        let s: Schedule = serde_json::from_str(&json).unwrap();
+        assert_eq!(s.active(), "holidays");
+        assert_eq!(s.setpoint_at(day, t), 20.0.into());
+    }
+
+    #[test]
+    fn test_curve() {
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2025, 1, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let clock = ManualClock::new(at(6, 10, 0));
+        let mut s = Schedule::default();
+        assert_eq!(s.setpoint_on(at(6, 10, 0)), 17.0.into());
+        assert_eq!(s.next_change(at(6, 10, 0)), (at(6, 22, 0), 17.0.into()));
+        assert_eq!(s.next_change(at(6, 22, 0)), (at(7, 0, 0), 14.0.into()));
+        assert_eq!(s.next_change(at(7, 0, 0)), (at(7, 5, 0), 15.5.into()));
+
+        let curve = s.curve(at(6, 0, 0), at(7, 0, 0), TimeDelta::hours(1));
+        assert_eq!(curve.len(), 25);
+        assert_eq!(curve[0], (at(6, 0, 0), 14.0.into()));
+        assert_eq!(curve[12].1, 17.0.into());
+        assert_eq!(curve[23].1, 15.5.into());
+        // steps past the last representable time end the curve
+        let end = DateTime::<Utc>::MAX_UTC;
+        let curve = s.curve(at(6, 0, 0), end, TimeDelta::days(270_000 * 365));
+        assert_eq!(curve.len(), 1);
+        let curve = s.curve(end - TimeDelta::minutes(90), end, TimeDelta::hours(1));
+        assert_eq!(curve.len(), 2);
+
+        // overrides count
+        s.set_override(Temperature::from(20.0), Some(at(6, 12, 0)), &clock)
+            .unwrap();
+        assert_eq!(s.setpoint_on(at(6, 11, 0)), 20.0.into());
+        assert_eq!(s.next_change(at(6, 11, 0)), (at(6, 12, 0), 17.0.into()));
+        assert_eq!(s.setpoint_on(at(6, 12, 0)), 17.0.into());
+    }
+
+    #[test]
+    fn test_dst() {
+        let paris: Tz = "Europe/Paris".parse().unwrap();
+        let utc = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let mut s = Schedule::new(16.0.into());
+        s.set_timezone(paris);
+        for (time, setpoint) in [("02:00", 16.0), ("02:30", 20.0), ("05:00", 20.0)] {
+            let time: Anchor = time.parse().unwrap();
+            s.insert(None, time, Temperature::from(setpoint)).unwrap();
+        }
+
+        // clocks jump from 2:00 to 3:00, and the 2:30 point with them
+        assert_eq!(s.setpoint_on(utc("2025-03-30T00:59:00Z")), 16.0.into());
+        assert_eq!(s.setpoint_on(utc("2025-03-30T01:00:00Z")), 20.0.into());
+        assert_eq!(
+            s.next_change(utc("2025-03-30T00:30:00Z")),
+            (utc("2025-03-30T01:00:00Z"), 20.0.into())
+        );
+
+        // clocks go back from 3:00 to 2:00: the 2:30 point happens once, and
+        // the setpoint doesn't start over during the repeated hour
+        let curve = s.curve(
+            utc("2025-10-26T00:00:00Z"),
+            utc("2025-10-26T02:00:00Z"),
+            TimeDelta::minutes(15),
+        );
+        let setpoints: Vec<f64> = curve.into_iter().map(|(_, t)| t.into()).collect();
+        assert_eq!(setpoints[..3], [16.0, 18.0, 20.0]);
+        assert!(setpoints[3..].iter().all(|t| *t == 20.0));
+        assert_eq!(
+            s.next_change(utc("2025-10-26T00:15:00Z")),
+            (utc("2025-10-26T00:30:00Z"), 20.0.into())
+        );
+        assert_eq!(
+            s.next_change(utc("2025-10-26T01:15:00Z")),
+            (utc("2025-10-26T04:00:00Z"), 20.0.into())
+        );
+
+        // overrides until the next point too
+        let clock = ManualClock::new(utc("2025-03-30T00:30:00Z"));
+        s.set_override(Temperature::from(18.0), None, &clock)
+            .unwrap();
+        assert_eq!(
+            s.current_override(&clock).unwrap().until,
+            utc("2025-03-30T01:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_solar_points() {
+        let sunset: Anchor = "sunset-30".parse().unwrap();
+        assert_eq!(sunset, Anchor::Sunset(-30));
+        assert_eq!(sunset.to_string(), "sunset-30");
+        assert_eq!("sunrise".parse(), Ok(Anchor::Sunrise(0)));
+        assert_eq!(
+            "07:30".parse(),
+            Ok(Anchor::Time(Time::from_minutes(450).unwrap()))
+        );
+        assert_eq!(
+            "450".parse(),
+            Ok(Anchor::Time(Time::from_minutes(450).unwrap()))
+        );
+        assert!("sunset30".parse::<Anchor>().is_err());
+        assert_eq!("sunrise+1440".parse(), Ok(Anchor::Sunrise(1440)));
+        assert!("sunrise+1441".parse::<Anchor>().is_err());
+        assert!("sunrise+2147483647".parse::<Anchor>().is_err());
+        assert!("sunset-2147483648".parse::<Anchor>().is_err());
+        assert!("07:30:15".parse::<Anchor>().is_err());
+
+        let mut s = Schedule::new(16.0.into());
+        assert_eq!(
+            s.insert(None, sunset, Temperature::from(20.0)),
+            Err(ScheduleError::NoLocation)
+        );
+        let paris = Location {
+            latitude: 48.85,
+            longitude: 2.35,
+        };
+        s.set_location(Some(paris));
+        s.insert(None, sunset, Temperature::from(20.0)).unwrap();
+
+        // the point follows the seasons
+        let time = |date| {
+            let set = paris.sun(date).unwrap().1;
+            Time::from_minutes(Time::from(set).minutes() - 30).unwrap()
+        };
+        let summer = NaiveDate::from_ymd_opt(2025, 6, 21).unwrap();
+        let winter = NaiveDate::from_ymd_opt(2025, 12, 21).unwrap();
+        assert_ne!(time(summer), time(winter));
+        for date in [summer, winter] {
+            assert_eq!(s.day(date)[1], (time(date), 20.0.into()));
+            assert_eq!(s.setpoint_at(date, time(date)), 20.0.into());
+        }
+
+        // no sunset during polar nights
+        s.set_location(Some(Location {
+            latitude: 69.65,
+            longitude: 18.96,
+        }));
+        assert_eq!(s.day(winter).len(), 2);
+        assert_eq!(
+            s.setpoint_at(winter, Time::from_hours_unchecked(12.0)),
+            16.0.into()
+        );
+
+        // times stay minutes in schedule.json
+        let json = serde_json::to_string(&s).unwrap();
+        assert!(json.contains(r#""0":16.0"#));
+        assert!(json.contains(r#""sunset-30":20.0"#));
+        let s: Schedule = serde_json::from_str(&json).unwrap();
+        assert_eq!(s.points().count(), 3);
     }
 }
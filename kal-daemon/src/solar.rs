@@ -0,0 +1,162 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolarConfig {
+    /// key where the exported power is published, in W
+    pub key: String,
+    /// boost when the export is above this, in W
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// setpoint raise while boosting, in °C
+    #[serde(default = "default_offset")]
+    pub offset: f64,
+    /// how long the export must stay on the other side of the threshold
+    #[serde(default = "default_debounce", with = "humantime_serde")]
+    pub debounce: Duration,
+    /// shortest boost
+    #[serde(default = "default_min_on", with = "humantime_serde")]
+    pub min_on: Duration,
+    /// shortest pause between two boosts
+    #[serde(default = "default_min_off", with = "humantime_serde")]
+    pub min_off: Duration,
+}
+
+fn default_threshold() -> f64 {
+    1000.0
+}
+
+fn default_offset() -> f64 {
+    2.0
+}
+
+fn default_debounce() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_min_on() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+fn default_min_off() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SolarState {
+    /// last exported power, in W
+    pub export: Option<f64>,
+    pub boost: bool,
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Store PV surplus as heat, without following every cloud.
+#[derive(Debug)]
+pub struct Solar {
+    pub config: SolarConfig,
+    /// side of the threshold the export is on, and since when
+    crossed: Option<(bool, DateTime<Utc>)>,
+    state: SolarState,
+}
+
+impl Solar {
+    pub fn new(config: SolarConfig) -> Self {
+        Self {
+            config,
+            crossed: None,
+            state: SolarState {
+                export: None,
+                boost: false,
+                since: None,
+            },
+        }
+    }
+
+    pub fn state(&self) -> SolarState {
+        self.state
+    }
+
+    /// Setpoint offset, if boosting.
+    pub fn offset(&self) -> Option<f64> {
+        self.state.boost.then_some(self.config.offset)
+    }
+
+    /// Record an export reading. Return true when the boost starts or stops.
+    pub fn update(&mut self, export: f64, now: DateTime<Utc>) -> bool {
+        self.state.export = Some(export);
+        let above = export > self.config.threshold;
+        if self.crossed.is_none_or(|(side, _)| side != above) {
+            self.crossed = Some((above, now));
+        }
+        self.poll(now)
+    }
+
+    /// Start or stop the boost once debounced, and after the minimum duration.
+    /// Return true when it does.
+    pub fn poll(&mut self, now: DateTime<Utc>) -> bool {
+        let Some((above, crossed)) = self.crossed else {
+            return false;
+        };
+        let min = match self.state.boost {
+            true => self.config.min_on,
+            false => self.config.min_off,
+        };
+        let elapsed = |t: DateTime<Utc>, d: Duration| now - t >= TimeDelta::from_std(d).unwrap();
+        if above != self.state.boost
+            && elapsed(crossed, self.config.debounce)
+            && self.state.since.is_none_or(|since| elapsed(since, min))
+        {
+            self.state.boost = above;
+            self.state.since = Some(now);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solar() {
+        let mut solar = Solar::new(SolarConfig {
+            key: "solar/export".to_string(),
+            threshold: default_threshold(),
+            offset: default_offset(),
+            debounce: default_debounce(),
+            min_on: default_min_on(),
+            min_off: default_min_off(),
+        });
+        let t0 = Utc::now();
+        let at = |m| t0 + TimeDelta::minutes(m);
+
+        // a passing surplus is ignored
+        assert!(!solar.update(1500.0, at(0)));
+        assert!(!solar.update(500.0, at(3)));
+        assert!(!solar.poll(at(10)));
+        assert_eq!(solar.offset(), None);
+
+        // a lasting one boosts
+        assert!(!solar.update(1500.0, at(10)));
+        assert!(!solar.poll(at(14)));
+        assert!(solar.poll(at(15)));
+        assert_eq!(solar.offset(), Some(2.0));
+
+        // a cloud doesn't stop it, and it lasts at least min_on
+        assert!(!solar.update(200.0, at(20)));
+        assert!(!solar.update(1500.0, at(22)));
+        assert!(!solar.update(200.0, at(30)));
+        assert!(!solar.poll(at(44)));
+        assert!(solar.poll(at(45)));
+        assert_eq!(solar.offset(), None);
+
+        // and then pauses at least min_off
+        assert!(!solar.update(1500.0, at(46)));
+        assert!(!solar.poll(at(55)));
+        assert!(solar.poll(at(60)));
+        assert_eq!(solar.state().since, Some(at(60)));
+    }
+}
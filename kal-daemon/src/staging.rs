@@ -0,0 +1,198 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Extra heating output, engaged after the zone relay when it's not enough.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Stage {
+    /// device subscribed to `kal/cmnd/<relay>/relay`
+    pub relay: String,
+    /// power, in kW, for energy reports
+    #[serde(default)]
+    pub power: f64,
+    /// engage when the error stays above this, in °C…
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// …for this long
+    #[serde(default = "default_delay", with = "humantime_serde")]
+    pub delay: Duration,
+    /// or when the previous stage has been on for this long
+    #[serde(default, with = "humantime_serde")]
+    pub keep_up: Option<Duration>,
+    /// release when the error stays below this, in °C…
+    #[serde(default = "default_release")]
+    pub release: f64,
+    /// …for this long
+    #[serde(default = "default_destage_delay", with = "humantime_serde")]
+    pub destage_delay: Duration,
+    /// last relay command
+    #[serde(skip_deserializing)]
+    pub on: Option<bool>,
+    /// since when it is on
+    #[serde(skip)]
+    since: Option<DateTime<Utc>>,
+    /// since when the error is above `threshold`
+    #[serde(skip)]
+    above: Option<DateTime<Utc>>,
+    /// since when the error is below `release`
+    #[serde(skip)]
+    below: Option<DateTime<Utc>>,
+}
+
+fn default_threshold() -> f64 {
+    1.5
+}
+
+fn default_delay() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+fn default_release() -> f64 {
+    0.5
+}
+
+fn default_destage_delay() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+fn elapsed(since: Option<DateTime<Utc>>, d: Duration, now: DateTime<Utc>) -> bool {
+    since.is_some_and(|t| now - t >= TimeDelta::from_std(d).unwrap())
+}
+
+impl Stage {
+    pub fn new(relay: &str) -> Self {
+        Self {
+            relay: relay.to_string(),
+            power: 0.0,
+            threshold: default_threshold(),
+            delay: default_delay(),
+            keep_up: None,
+            release: default_release(),
+            destage_delay: default_destage_delay(),
+            on: None,
+            since: None,
+            above: None,
+            below: None,
+        }
+    }
+
+    pub fn key(&self) -> String {
+        format!("kal/cmnd/{}/relay", self.relay)
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on == Some(true)
+    }
+
+    fn set(&mut self, on: bool, now: DateTime<Utc>) {
+        self.on = Some(on);
+        self.since = on.then_some(now);
+    }
+}
+
+/// Engage or release `stages` in order, from the heating demand, the error
+/// (setpoint - temperature), and since when the zone relay is on.
+/// Return the stages to switch, with the reason.
+pub fn update(
+    stages: &mut [Stage],
+    demand: Option<DateTime<Utc>>,
+    error: f64,
+    now: DateTime<Utc>,
+) -> Vec<(usize, bool, String)> {
+    let mut changes = Vec::new();
+    let mut previous = demand;
+    for (i, stage) in stages.iter_mut().enumerate() {
+        let want = match previous {
+            None => {
+                stage.above = None;
+                stage.below = None;
+                (false, "previous stage off".to_string())
+            }
+            Some(previous) => {
+                if error > stage.threshold {
+                    stage.above.get_or_insert(now);
+                } else {
+                    stage.above = None;
+                }
+                if error < stage.release {
+                    stage.below.get_or_insert(now);
+                } else {
+                    stage.below = None;
+                }
+                let keep_up = stage.keep_up.filter(|d| elapsed(Some(previous), *d, now));
+                match stage.is_on() {
+                    false if elapsed(stage.above, stage.delay, now) => {
+                        (true, format!("error {error:.1}°C > {}°C", stage.threshold))
+                    }
+                    false if keep_up.is_some() => (
+                        true,
+                        format!(
+                            "previous stage on for {}",
+                            humantime::format_duration(keep_up.unwrap())
+                        ),
+                    ),
+                    true if elapsed(stage.below, stage.destage_delay, now) => {
+                        (false, format!("error {error:.1}°C < {}°C", stage.release))
+                    }
+                    on => (on, String::new()),
+                }
+            }
+        };
+        if stage.on != Some(want.0) {
+            stage.set(want.0, now);
+            changes.push((i, want.0, want.1));
+        }
+        previous = stage.since;
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staging() {
+        let t0 = Utc::now();
+        let at = |m| t0 + TimeDelta::minutes(m);
+        let mut stages = vec![
+            Stage::new("resistance"),
+            Stage {
+                keep_up: Some(Duration::from_secs(3600)),
+                threshold: 10.0,
+                ..Stage::new("boiler")
+            },
+        ];
+        let on = |stages: &[Stage]| stages.iter().map(|s| s.is_on()).collect::<Vec<_>>();
+
+        // without demand, everything is switched off once
+        assert_eq!(update(&mut stages, None, 0.0, at(0)).len(), 2);
+        assert!(update(&mut stages, None, 0.0, at(0)).is_empty());
+
+        // a large error engages stage 2 after its delay
+        let demand = Some(at(0));
+        assert!(update(&mut stages, demand, 2.0, at(0)).is_empty());
+        assert!(update(&mut stages, demand, 2.0, at(29)).is_empty());
+        let changes = update(&mut stages, demand, 2.0, at(30));
+        assert_eq!(changes[0].0, 0);
+        assert!(changes[0].1);
+        assert_eq!(on(&stages), [true, false]);
+
+        // stage 3 engages when stage 2 can't keep up
+        assert!(update(&mut stages, demand, 1.0, at(89)).is_empty());
+        assert_eq!(update(&mut stages, demand, 1.0, at(90))[0].0, 1);
+        assert_eq!(on(&stages), [true, true]);
+
+        // small errors release them after the delay
+        assert!(update(&mut stages, demand, 0.2, at(100)).is_empty());
+        assert_eq!(update(&mut stages, demand, 0.2, at(110)).len(), 2);
+        assert_eq!(on(&stages), [false, false]);
+
+        // no demand releases at once
+        update(&mut stages, demand, 2.0, at(120));
+        update(&mut stages, demand, 2.0, at(150));
+        assert_eq!(on(&stages), [true, false]);
+        assert_eq!(update(&mut stages, None, -0.1, at(151)).len(), 1);
+    }
+}
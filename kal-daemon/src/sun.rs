@@ -0,0 +1,80 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+
+/// Where the house is, to compute sunrise and sunset.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    /// in degrees, north positive
+    pub latitude: f64,
+    /// in degrees, east positive
+    pub longitude: f64,
+}
+
+/// Julian date of 2000-01-01 12:00 UTC
+const J2000: f64 = 2451545.0;
+/// Julian date of the unix epoch
+const UNIX: f64 = 2440587.5;
+
+fn from_julian(j: f64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(((j - UNIX) * 86_400_000.0) as i64).unwrap()
+}
+
+impl Location {
+    /// Sunrise and sunset on `date`, or None during polar days and nights,
+    /// from the sunrise equation, within a few minutes.
+    pub fn sun(&self, date: NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let n = (date - epoch).num_days() as f64;
+        // mean solar time at noon
+        let j = n - self.longitude / 360.0;
+        let m = (357.5291 + 0.98560028 * j).rem_euclid(360.0).to_radians();
+        // equation of the center
+        let c = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+        let lambda = (m.to_degrees() + c + 180.0 + 102.9372)
+            .rem_euclid(360.0)
+            .to_radians();
+        let transit = J2000 + j + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+        let declination = (lambda.sin() * 23.4397_f64.to_radians().sin()).asin();
+        let latitude = self.latitude.to_radians();
+        let cos_hour = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        if !(-1.0..=1.0).contains(&cos_hour) {
+            return None;
+        }
+        let hour = cos_hour.acos().to_degrees() / 360.0;
+        Some((from_julian(transit - hour), from_julian(transit + hour)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_sun() {
+        let paris = Location {
+            latitude: 48.85,
+            longitude: 2.35,
+        };
+        let near = |a: DateTime<Utc>, b: &str| {
+            let b = b.parse::<DateTime<Utc>>().unwrap();
+            (a - b).abs() < TimeDelta::minutes(3)
+        };
+        let day = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+        let (rise, set) = paris.sun(day(6, 21)).unwrap();
+        assert!(near(rise, "2025-06-21T03:47:00Z"));
+        assert!(near(set, "2025-06-21T19:58:00Z"));
+        let (rise, set) = paris.sun(day(12, 21)).unwrap();
+        assert!(near(rise, "2025-12-21T07:42:00Z"));
+        assert!(near(set, "2025-12-21T15:56:00Z"));
+
+        let tromso = Location {
+            latitude: 69.65,
+            longitude: 18.96,
+        };
+        assert!(tromso.sun(day(6, 21)).is_none());
+        assert!(tromso.sun(day(12, 21)).is_none());
+    }
+}
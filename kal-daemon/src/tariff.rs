@@ -0,0 +1,283 @@
+use chrono::{DateTime, Datelike, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::time::hhmm;
+use crate::{Room, Schedule, Temperature, Time};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TariffConfig {
+    /// calendar file, in TOML
+    pub file: Option<PathBuf>,
+    /// key where a JSON calendar is published
+    pub key: Option<String>,
+    /// how far the setpoint may be moved, in °C
+    pub tolerance: f64,
+    /// how long before a peak period to start preheating
+    #[serde(with = "humantime_serde")]
+    pub preheat: Duration,
+}
+
+impl Default for TariffConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            key: None,
+            tolerance: 1.0,
+            preheat: Duration::from_secs(2 * 3600),
+        }
+    }
+}
+
+impl TariffConfig {
+    /// Peaks are looked for every 5 minutes of `preheat`, on every decision.
+    pub fn check(&self) -> Result<(), String> {
+        if !(0.0..=5.0).contains(&self.tolerance) {
+            return Err(format!(
+                "tariff tolerance must be from 0 to 5°C, not {}",
+                self.tolerance
+            ));
+        }
+        if self.preheat > Duration::from_secs(24 * 3600) {
+            return Err(format!(
+                "tariff preheat must be at most a day, not {}",
+                humantime::format_duration(self.preheat)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Price from `start` to `end` (which may wrap around midnight), on some days.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Slot {
+    #[serde(deserialize_with = "hhmm")]
+    pub start: Time,
+    #[serde(deserialize_with = "hhmm")]
+    pub end: Time,
+    pub price: f64,
+    /// every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+}
+
+impl Slot {
+    fn contains(&self, day: Weekday, t: Time) -> bool {
+        let day_ok = self.days.is_empty() || self.days.contains(&day);
+        if self.start <= self.end {
+            day_ok && self.start <= t && t < self.end
+        } else {
+            // wrapping slots belong to the day they start
+            let yesterday = self.days.is_empty() || self.days.contains(&day.pred());
+            (day_ok && self.start <= t) || (yesterday && t < self.end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Calendar {
+    /// price outside of any slot
+    pub default: f64,
+    #[serde(default)]
+    pub slots: Vec<Slot>,
+}
+
+impl Calendar {
+    /// Price at `t`, with slots on the wall clock of `tz`.
+    pub fn price(&self, t: DateTime<Utc>, tz: Tz) -> f64 {
+        let (date, time) = Time::at(t, tz);
+        self.slots
+            .iter()
+            .find(|s| s.contains(date.weekday(), time))
+            .map_or(self.default, |s| s.price)
+    }
+    /// Prices above the middle of the range are peak prices.
+    pub fn is_peak(&self, t: DateTime<Utc>, tz: Tz) -> bool {
+        let prices = || self.slots.iter().map(|s| s.price).chain([self.default]);
+        let min = prices().fold(f64::INFINITY, f64::min);
+        let max = prices().fold(f64::NEG_INFINITY, f64::max);
+        max > min && self.price(t, tz) > (min + max) / 2.0
+    }
+}
+
+/// A period of the planned relay timeline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub on: bool,
+    /// predicted temperature at the end of the segment
+    pub temperature: f64,
+    pub cost: f64,
+}
+
+/// Shift heating out of peak periods, within a comfort tolerance.
+#[derive(Debug)]
+pub struct Tariff {
+    pub config: TariffConfig,
+    pub calendar: Calendar,
+    pub timezone: Tz,
+}
+
+impl Tariff {
+    pub fn new(config: TariffConfig, calendar: Calendar, timezone: Tz) -> Self {
+        Self {
+            config,
+            calendar,
+            timezone,
+        }
+    }
+
+    pub fn price(&self, t: DateTime<Utc>) -> f64 {
+        self.calendar.price(t, self.timezone)
+    }
+    pub fn is_peak(&self, t: DateTime<Utc>) -> bool {
+        self.calendar.is_peak(t, self.timezone)
+    }
+
+    /// Setpoint offset: +tolerance shortly before a peak, -tolerance during it.
+    pub fn offset(&self, t: DateTime<Utc>) -> f64 {
+        if self.is_peak(t) {
+            return -self.config.tolerance;
+        }
+        let step = TimeDelta::minutes(5);
+        let preheat = TimeDelta::from_std(self.config.preheat).unwrap();
+        let mut ahead = step;
+        while ahead <= preheat {
+            if self.is_peak(t + ahead) {
+                return self.config.tolerance;
+            }
+            ahead += step;
+        }
+        0.0
+    }
+
+    /// Predict the relay timeline of a zone modeled by `room`.
+    pub fn plan(
+        &self,
+        schedule: &Schedule,
+        mut room: Room,
+        from: DateTime<Utc>,
+        horizon: TimeDelta,
+    ) -> Vec<Segment> {
+        let step = TimeDelta::minutes(5);
+        let kw = room.heater_power / 1000.0;
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut t = from;
+        while t < from + horizon {
+            let setpoint = match schedule.override_at(t) {
+                Some(o) => o.temperature,
+                None => {
+                    let (date, time) = Time::at(t, schedule.timezone());
+                    schedule.setpoint_at(date, time) + Temperature::from(self.offset(t))
+                }
+            };
+            room.heating = room.temperature() < setpoint;
+            room.step(step.to_std().unwrap());
+            let cost = if room.heating {
+                kw * step.as_seconds_f64() / 3600.0 * self.price(t)
+            } else {
+                0.0
+            };
+            match segments.last_mut() {
+                Some(last) if last.on == room.heating => {
+                    last.end = t + step;
+                    last.temperature = room.temperature;
+                    last.cost += cost;
+                }
+                _ => segments.push(Segment {
+                    start: t,
+                    end: t + step,
+                    on: room.heating,
+                    temperature: room.temperature,
+                    cost,
+                }),
+            }
+            t += step;
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_tariff() {
+        // peak from 17:00 to 21:00 on weekdays, off-peak at night
+        let calendar: Calendar = toml::from_str(
+            r#"
+            default = 0.20
+            [[slots]]
+            start = "17:00"
+            end = "21:00"
+            price = 0.40
+            days = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            [[slots]]
+            start = "22:00"
+            end = "06:00"
+            price = 0.15
+            "#,
+        )
+        .unwrap();
+        let paris: Tz = "Europe/Paris".parse().unwrap();
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2025, 1, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+                .and_local_timezone(paris)
+                .unwrap()
+                .to_utc()
+        };
+        // monday 6th
+        assert_eq!(calendar.price(at(6, 12, 0), paris), 0.20);
+        assert_eq!(calendar.price(at(6, 17, 0), paris), 0.40);
+        assert_eq!(calendar.price(at(6, 23, 0), paris), 0.15);
+        assert_eq!(calendar.price(at(7, 5, 59), paris), 0.15);
+        // saturday 11th
+        assert_eq!(calendar.price(at(11, 18, 0), paris), 0.20);
+        assert!(calendar.is_peak(at(6, 18, 0), paris));
+        assert!(!calendar.is_peak(at(6, 12, 0), paris));
+
+        let config = |c: &str| toml::from_str::<TariffConfig>(c).unwrap().check();
+        assert!(config("").is_ok());
+        assert!(config("preheat = \"2days\"").is_err());
+        assert!(config("tolerance = -1.0").is_err());
+        assert!(config("tolerance = nan").is_err());
+
+        let tariff = Tariff::new(TariffConfig::default(), calendar, paris);
+        assert_eq!(tariff.offset(at(6, 12, 0)), 0.0);
+        assert_eq!(tariff.offset(at(6, 15, 30)), 1.0);
+        assert_eq!(tariff.offset(at(6, 20, 55)), -1.0);
+        assert_eq!(tariff.offset(at(11, 15, 30)), 0.0);
+
+        let mut schedule = Schedule::new(19.0.into());
+        schedule.set_timezone(paris);
+        let room = Room {
+            temperature: 19.0,
+            outdoor: 10.0,
+            ..Room::default()
+        };
+        let plan = tariff.plan(&schedule, room, at(6, 12, 0), TimeDelta::hours(12));
+        assert_eq!(plan.first().unwrap().start, at(6, 12, 0));
+        assert_eq!(plan.last().unwrap().end, at(7, 0, 0));
+        // preheat before the peak, coast during it
+        let during = |t| plan.iter().find(|s| s.start <= t && t < s.end).unwrap();
+        assert!(during(at(6, 16, 55)).on || during(at(6, 16, 50)).on);
+        assert!(!during(at(6, 17, 30)).on);
+        let peak_cost: f64 = plan
+            .iter()
+            .filter(|s| tariff.is_peak(s.start))
+            .map(|s| s.cost)
+            .sum();
+        assert!(peak_cost < plan.iter().map(|s| s.cost).sum::<f64>() / 2.0);
+    }
+}
@@ -8,7 +8,10 @@
     derive_more::Sub,
     derive_more::Mul,
     derive_more::From,
+    serde::Serialize,
+    serde::Deserialize,
 )]
+#[serde(transparent)]
 pub struct Temperature(f64);
 
 impl std::fmt::Display for Temperature {
@@ -16,3 +19,9 @@ impl std::fmt::Display for Temperature {
         write!(f, "{}°C", self.0)
     }
 }
+
+impl From<Temperature> for f64 {
+    fn from(value: Temperature) -> Self {
+        value.0
+    }
+}
@@ -1,6 +1,12 @@
-use chrono::Timelike;
+use chrono::{
+    DateTime, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
+/// Wall clock time of the day, in seconds since midnight, 24H00 included.
 #[derive(
     Debug,
     Copy,
@@ -30,40 +36,186 @@ pub enum TimeError {
 
 pub type TimeResult = Result<Time, TimeError>;
 
+/// Timezone of the host, or UTC if it can't be found.
+pub fn host_timezone() -> Tz {
+    match iana_time_zone::get_timezone() {
+        Ok(name) => name.parse().unwrap_or_else(|e| {
+            warn!("unknown host timezone {name}: {e}, using UTC");
+            Tz::UTC
+        }),
+        Err(e) => {
+            warn!("can't find the host timezone: {e}, using UTC");
+            Tz::UTC
+        }
+    }
+}
+
+/// First minute after `local` that happens exactly once in `tz`: the end of
+/// the gap or fold it is in.
+fn settled(tz: Tz, local: NaiveDateTime) -> NaiveDateTime {
+    let minute = local.with_second(0).unwrap().with_nanosecond(0).unwrap();
+    (1..)
+        .map(|m| minute + TimeDelta::minutes(m))
+        .find(|l| matches!(tz.from_local_datetime(l), LocalResult::Single(_)))
+        .unwrap()
+}
+
 impl Time {
     pub const MIN: Self = Self(0);
-    pub const MAX: Self = Self(24 * 60);
+    pub const MAX: Self = Self(24 * 3600);
 
-    pub fn now() -> Self {
-        chrono::Local::now().into()
+    /// Date and time of the day of `t` on the wall clocks of `tz`. When
+    /// clocks go back, the repeated hour only happens once: the time stays
+    /// at its last second until the wall clock catches up.
+    pub fn at(t: DateTime<Utc>, tz: Tz) -> (NaiveDate, Self) {
+        let local = t.with_timezone(&tz);
+        let wall = match tz.from_local_datetime(&local.naive_local()) {
+            LocalResult::Ambiguous(first, _) if first != local => {
+                settled(tz, local.naive_local()) - TimeDelta::seconds(1)
+            }
+            _ => local.naive_local(),
+        };
+        (wall.date(), wall.time().into())
     }
-    pub fn from_minutes(minutes: u32) -> TimeResult {
-        if minutes <= 24 * 60 {
-            Ok(Self(minutes))
+    /// This time of the day on `date` in `tz`, 24H00 being the next midnight.
+    /// Times repeated when clocks go back are their first occurrence, and
+    /// times skipped when they go forward are the moment of the jump.
+    pub fn on(self, date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+        let local = date.and_time(NaiveTime::MIN) + TimeDelta::seconds(self.0.into());
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.to_utc(),
+            LocalResult::None => tz
+                .from_local_datetime(&settled(tz, local))
+                .unwrap()
+                .to_utc(),
+        }
+    }
+    pub fn from_seconds(seconds: u32) -> TimeResult {
+        if seconds <= Self::MAX.0 {
+            Ok(Self(seconds))
         } else {
             Err(TimeError::Wrong)
         }
     }
+    pub fn from_minutes(minutes: u32) -> TimeResult {
+        Self::from_seconds(minutes.checked_mul(60).ok_or(TimeError::Wrong)?)
+    }
     pub fn from_hours(hours: f64) -> TimeResult {
-        if 0.0 <= hours && hours <= 24.0 {
+        if (0.0..=24.0).contains(&hours) {
             Ok(Self::from_hours_unchecked(hours))
         } else {
             Err(TimeError::Wrong)
         }
     }
     pub fn from_hours_unchecked(hours: f64) -> Time {
-        Self((hours * 60.0) as u32)
+        Self((hours * 3600.0) as u32)
+    }
+    pub fn seconds(self) -> u32 {
+        self.0
+    }
+    /// Whole minutes
+    pub fn minutes(self) -> u32 {
+        self.0 / 60
+    }
+}
+
+/// As minutes, like schedule points in `schedule.json`
+impl Serialize for Time {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.minutes().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Time {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::from_minutes(u32::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Deserialize a time from `HH:MM`, rather than minutes.
+pub(crate) fn hhmm<'de, D: Deserializer<'de>>(d: D) -> Result<Time, D::Error> {
+    let s = String::deserialize(d)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Parse `HH:MM`, or `HH:MM:SS`
+impl std::str::FromStr for Time {
+    type Err = TimeError;
+    fn from_str(s: &str) -> TimeResult {
+        let mut parts = s
+            .split(':')
+            .map(|p| p.parse::<u32>().map_err(|_| TimeError::Wrong));
+        let h = parts.next().ok_or(TimeError::Wrong)??;
+        let m = parts.next().ok_or(TimeError::Wrong)??;
+        let s = parts.next().transpose()?.unwrap_or(0);
+        if h > 24 || m >= 60 || s >= 60 || parts.next().is_some() {
+            return Err(TimeError::Wrong);
+        }
+        Self::from_seconds(h * 3600 + m * 60 + s)
     }
 }
 
 impl<T: Timelike> From<T> for Time {
     fn from(time: T) -> Self {
-        Self(time.minute() + time.hour() * 60)
+        Self(time.num_seconds_from_midnight())
     }
 }
 
 impl std::fmt::Display for Time {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:02}H{:02}", self.0 / 60, self.0 % 60)
+        write!(f, "{:02}H{:02}", self.0 / 3600, self.0 / 60 % 60)?;
+        match self.0 % 60 {
+            0 => Ok(()),
+            s => write!(f, "{s:02}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dst() {
+        let paris: Tz = "Europe/Paris".parse().unwrap();
+        let utc = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let time = |s: &str| s.parse::<Time>().unwrap();
+        assert_eq!(time("07:30:15").seconds(), 7 * 3600 + 30 * 60 + 15);
+        assert_eq!(time("07:30:15").to_string(), "07H3015");
+        assert!("07:60".parse::<Time>().is_err());
+        assert_eq!(time("24:00"), Time::MAX);
+        assert!("24:01".parse::<Time>().is_err());
+        assert!("1193047:00".parse::<Time>().is_err());
+
+        // clocks jump from 2:00 to 3:00: 2:30 happens at the jump
+        let spring = NaiveDate::from_ymd_opt(2025, 3, 30).unwrap();
+        assert_eq!(time("01:30").on(spring, paris), utc("2025-03-30T00:30:00Z"));
+        assert_eq!(time("02:30").on(spring, paris), utc("2025-03-30T01:00:00Z"));
+        assert_eq!(time("03:00").on(spring, paris), utc("2025-03-30T01:00:00Z"));
+        assert_eq!(
+            Time::at(utc("2025-03-30T01:00:00Z"), paris),
+            (spring, time("03:00"))
+        );
+
+        // clocks go back from 3:00 to 2:00: 2:30 happens the first time, and
+        // the time waits for 3:00 during the second
+        let fall = NaiveDate::from_ymd_opt(2025, 10, 26).unwrap();
+        assert_eq!(time("02:30").on(fall, paris), utc("2025-10-26T00:30:00Z"));
+        assert_eq!(time("03:00").on(fall, paris), utc("2025-10-26T02:00:00Z"));
+        assert_eq!(
+            Time::at(utc("2025-10-26T00:30:00Z"), paris),
+            (fall, time("02:30"))
+        );
+        assert_eq!(
+            Time::at(utc("2025-10-26T01:30:00Z"), paris),
+            (fall, time("02:59:59"))
+        );
+        assert_eq!(
+            Time::at(utc("2025-10-26T02:30:00Z"), paris),
+            (fall, time("03:30"))
+        );
+
+        // 24H00 is the next midnight
+        assert_eq!(Time::MAX.on(fall, paris), utc("2025-10-26T23:00:00Z"));
     }
 }
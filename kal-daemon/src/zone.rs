@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Action, Limit, Room, Run, Stage, Temperature};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Zone {
+    pub name: String,
+    /// device publishing `kal/tele/<sensor>/temperature`
+    pub sensor: String,
+    /// device subscribed to `kal/cmnd/<relay>/relay`
+    pub relay: String,
+    /// device subscribed to `kal/cmnd/<cooler>/relay`, for an AC unit
+    #[serde(default)]
+    pub cooler: Option<String>,
+    /// extra heating outputs, engaged in order
+    #[serde(default)]
+    pub stages: Vec<Stage>,
+    /// thermal model, to plan ahead
+    #[serde(default, skip_serializing)]
+    pub model: Room,
+    #[serde(skip_deserializing)]
+    pub temperature: Option<Temperature>,
+    /// time of the last temperature reading
+    #[serde(skip_deserializing)]
+    pub updated: Option<DateTime<Utc>>,
+    /// last relay command
+    #[serde(skip_deserializing)]
+    pub heating: Option<bool>,
+    /// since when the relay is on
+    #[serde(skip)]
+    pub heating_since: Option<DateTime<Utc>>,
+    /// last cooler command
+    #[serde(skip_deserializing)]
+    pub cooling: Option<bool>,
+    /// limit the temperature is beyond
+    #[serde(skip_deserializing)]
+    pub limit: Option<Limit>,
+    /// last relay state reported by the device
+    #[serde(skip_deserializing)]
+    pub reported: Option<bool>,
+    /// since when the reported state differs from the command
+    #[serde(skip)]
+    pub mismatch_since: Option<DateTime<Utc>>,
+    /// temperature response while heating
+    #[serde(skip)]
+    pub run: Option<Run>,
+    /// last audited decision, and what drove it
+    #[serde(skip)]
+    pub audited: Option<(Action, String)>,
+}
+
+impl Zone {
+    pub fn new(name: &str, sensor: &str, relay: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            sensor: sensor.to_string(),
+            relay: relay.to_string(),
+            cooler: None,
+            stages: Vec::new(),
+            model: Room::default(),
+            temperature: None,
+            updated: None,
+            heating: None,
+            heating_since: None,
+            cooling: None,
+            limit: None,
+            reported: None,
+            mismatch_since: None,
+            run: None,
+            audited: None,
+        }
+    }
+    pub fn uses(&self, device: &str) -> bool {
+        self.sensor == device
+            || self.relay == device
+            || self.cooler.as_deref() == Some(device)
+            || self.stages.iter().any(|s| s.relay == device)
+    }
+    pub fn relay_key(&self) -> String {
+        format!("kal/cmnd/{}/relay", self.relay)
+    }
+    pub fn cooler_key(&self) -> Option<String> {
+        let cooler = self.cooler.as_ref()?;
+        Some(format!("kal/cmnd/{cooler}/relay"))
+    }
+}
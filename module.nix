@@ -8,14 +8,21 @@ let
   moduleName = "kal";
   cfg = config.services."${moduleName}";
   secretInfluxDBToken = "please-use-sops-nix-or-agenix";
+  settingsFormat = pkgs.formats.toml { };
 in
 {
   options = {
     services."${moduleName}" = {
       enable = lib.mkEnableOption "${moduleName} service";
+      settings = lib.mkOption {
+        type = settingsFormat.type;
+        default = { };
+        description = "kal-daemon configuration, see kal-daemon/src/config.rs";
+      };
     };
   };
   config = lib.mkIf cfg.enable {
+    services."${moduleName}".settings.state_dir = lib.mkDefault "/var/lib/${moduleName}";
     services = {
       grafana = {
         enable = true;
@@ -123,10 +130,14 @@ in
         wantedBy = [ "multi-user.target" ];
 
         serviceConfig = {
-          Environment = "RUST_LOG=debug";
+          Environment = [
+            "RUST_LOG=debug"
+            "KAL_CONFIG=${settingsFormat.generate "kal.toml" cfg.settings}"
+          ];
           ExecStart = lib.getExe pkgs.kal-daemon;
           Type = "exec";
           DynamicUser = true;
+          StateDirectory = moduleName;
           Restart = "on-failure";
           RestartSec = 5;
         };